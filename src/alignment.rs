@@ -0,0 +1,214 @@
+//! Global sequence alignment of the GET and FRIB time stamp difference streams.
+//! This is a Needleman-Wunsch style dynamic programming alignment where the cost
+//! of matching two events is the jitter between the time elapsed since the previously
+//! matched pair on each stream, and every event left unmatched costs a gap penalty.
+use crate::config::SyncConfig;
+
+const INFINITE_COST: i64 = i64::MAX / 4;
+const START: (u8, u8) = (u8::MAX, u8::MAX);
+
+/// Accumulate the TS differences back into time stamps relative to the first event.
+fn accumulate(tsd: &[i64]) -> Vec<i64> {
+    let mut total = 0;
+    tsd.iter()
+        .map(|d| {
+            total += d;
+            total
+        })
+        .collect()
+}
+
+/// Align two TS difference streams (the first entry of each is expected to be 0).
+/// Returns the lists of matched indices for the GET and FRIB streams.
+///
+/// For each GET event, only the FRIB events within `params.band` of the best match of the
+/// previous GET event are considered, so the band follows the alignment as the offset between
+/// the streams grows. At most `params.max_gap` consecutive events can be skipped on each stream
+/// between two matches.
+///
+/// The back pointers of the whole run are kept: 2 × (2 × band + 1) bytes per GET event,
+/// about 400 MB for a million events with the default band of 100.
+pub fn align_global(
+    tsd_get: &[i64],
+    tsd_frib: &[i64],
+    params: &SyncConfig,
+) -> (Vec<usize>, Vec<usize>) {
+    let n_get = tsd_get.len();
+    let n_frib = tsd_frib.len();
+    if n_get == 0 || n_frib == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let ts_get = accumulate(tsd_get);
+    let ts_frib = accumulate(tsd_frib);
+
+    let band = params.band as i64;
+    let width = 2 * params.band + 1;
+    let max_gap = params.max_gap;
+    let gap = params.gap_penalty;
+
+    // Only the last max_gap + 1 rows of costs are needed, but all back pointers are kept.
+    // Row i covers the FRIB events from lows[i] to lows[i] + 2 * band.
+    let n_rows = max_gap + 2;
+    let mut costs = vec![vec![INFINITE_COST; width]; n_rows];
+    let mut back = vec![START; n_get * width];
+    let mut lows: Vec<i64> = Vec::with_capacity(n_get);
+    let mut previous_best: Option<usize> = None;
+    let mut best_end = INFINITE_COST;
+    let mut best_cell: Option<(usize, usize)> = None;
+
+    for i in 0..n_get {
+        // Center the band on the event following the best match of the previous row
+        let center = match previous_best {
+            Some(j) => j as i64 + 1,
+            None => lows.last().map_or(0, |low| low + band + 1),
+        };
+        let low = center - band;
+        lows.push(low);
+        let mut row = vec![INFINITE_COST; width];
+        let mut row_best = INFINITE_COST;
+        previous_best = None;
+        for (k, cell) in row.iter_mut().enumerate() {
+            let j = low + k as i64;
+            if j < 0 || j >= n_frib as i64 {
+                continue;
+            }
+            let j = j as usize;
+            // Start the alignment here, leaving all previous events unmatched
+            let mut best = (i + j) as i64 * gap;
+            let mut pointer = START;
+            for a in 0..=max_gap {
+                if i < a + 1 {
+                    break;
+                }
+                let pi = i - a - 1;
+                for b in 0..=max_gap {
+                    if j < b + 1 {
+                        break;
+                    }
+                    let pj = j - b - 1;
+                    let pk = pj as i64 - lows[pi];
+                    if pk < 0 || pk >= width as i64 {
+                        continue;
+                    }
+                    let previous = costs[pi % n_rows][pk as usize];
+                    if previous >= INFINITE_COST {
+                        continue;
+                    }
+                    let jitter = (ts_get[i] - ts_get[pi]) - (ts_frib[j] - ts_frib[pj]);
+                    let cost = previous + (a + b) as i64 * gap + jitter.abs();
+                    if cost < best {
+                        best = cost;
+                        pointer = (a as u8, b as u8);
+                    }
+                }
+            }
+            *cell = best;
+            back[i * width + k] = pointer;
+            if best < row_best {
+                row_best = best;
+                previous_best = Some(j);
+            }
+            // End the alignment here, leaving all following events unmatched
+            let end = best + ((n_get - 1 - i) + (n_frib - 1 - j)) as i64 * gap;
+            if end < best_end {
+                best_end = end;
+                best_cell = Some((i, k));
+            }
+        }
+        costs[i % n_rows] = row;
+    }
+
+    // Walk the back pointers to recover the matched pairs
+    let mut get_sync = Vec::new();
+    let mut frib_sync = Vec::new();
+    let mut cell = best_cell;
+    while let Some((i, k)) = cell {
+        let j = (lows[i] + k as i64) as usize;
+        get_sync.push(i);
+        frib_sync.push(j);
+        let pointer = back[i * width + k];
+        cell = if pointer == START {
+            None
+        } else {
+            let pi = i - pointer.0 as usize - 1;
+            let pj = j - pointer.1 as usize - 1;
+            Some((pi, (pj as i64 - lows[pi]) as usize))
+        };
+    }
+    get_sync.reverse();
+    frib_sync.reverse();
+    (get_sync, frib_sync)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time stamps of a stream of events with irregular spacing.
+    fn timestamps(n: usize) -> Vec<i64> {
+        let mut ts = 0;
+        (0..n)
+            .map(|k| {
+                ts += 1000 + (k as i64 * 7919 % 500) * 10;
+                ts
+            })
+            .collect()
+    }
+
+    /// The TS differences of the events that are kept, starting with 0.
+    fn differences(ts: &[i64], missing: &[usize]) -> Vec<i64> {
+        let kept: Vec<i64> = ts
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| !missing.contains(k))
+            .map(|(_, ts)| *ts)
+            .collect();
+        std::iter::once(0)
+            .chain(kept.windows(2).map(|pair| pair[1] - pair[0]))
+            .collect()
+    }
+
+    /// The expected matches of a stream with missing events against a complete one.
+    fn expected(n: usize, missing: &[usize]) -> (Vec<usize>, Vec<usize>) {
+        let complete: Vec<usize> = (0..n).filter(|k| !missing.contains(k)).collect();
+        ((0..complete.len()).collect(), complete)
+    }
+
+    #[test]
+    fn aligns_with_a_missing_get_event() {
+        let ts = timestamps(50);
+        let (get_sync, frib_sync) = align_global(
+            &differences(&ts, &[20]),
+            &differences(&ts, &[]),
+            &SyncConfig::default(),
+        );
+        assert_eq!((get_sync, frib_sync), expected(50, &[20]));
+    }
+
+    #[test]
+    fn aligns_with_a_missing_frib_event() {
+        let ts = timestamps(50);
+        let (frib_sync, get_sync) = expected(50, &[20]);
+        assert_eq!(
+            align_global(
+                &differences(&ts, &[]),
+                &differences(&ts, &[20]),
+                &SyncConfig::default()
+            ),
+            (get_sync, frib_sync)
+        );
+    }
+
+    #[test]
+    fn band_follows_an_offset_larger_than_the_band() {
+        let ts = timestamps(100);
+        let missing = [10, 25, 40, 55, 70];
+        let params = SyncConfig {
+            band: 2,
+            ..SyncConfig::default()
+        };
+        let (get_sync, frib_sync) =
+            align_global(&differences(&ts, &missing), &differences(&ts, &[]), &params);
+        assert_eq!((get_sync, frib_sync), expected(100, &missing));
+    }
+}
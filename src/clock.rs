@@ -0,0 +1,134 @@
+//! Linear model relating the GET and FRIB clocks.
+//! The GET time stamps are converted to the FRIB time base using this model
+//! before the two streams are compared.
+
+/// A linear piece of the clock model, valid from `get_start` until the start of the next piece.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockSegment {
+    pub get_start: u64,
+    pub frib_start: f64,
+    pub scale: f64,
+}
+
+impl ClockSegment {
+    /// The offset of this piece, such that frib = scale * get + offset.
+    pub fn offset(&self) -> f64 {
+        self.frib_start - self.scale * self.get_start as f64
+    }
+}
+
+/// Piecewise linear relation between the GET and FRIB time stamps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockModel {
+    pub segments: Vec<ClockSegment>,
+}
+
+impl ClockModel {
+    /// A model with a fixed ratio of FRIB ticks per GET tick and no offset.
+    pub fn nominal(ratio: f64) -> Self {
+        Self {
+            segments: vec![ClockSegment {
+                get_start: 0,
+                frib_start: 0.0,
+                scale: ratio,
+            }],
+        }
+    }
+
+    /// Fit the model to matched (GET, FRIB) time stamp pairs, ordered in time.
+    /// If `window` is 0 a single line is fit, otherwise one piece is fit per `window` pairs
+    /// and the pieces are joined so that the prediction stays continuous.
+    /// Returns None if there are not enough pairs to fit.
+    pub fn fit(pairs: &[(u64, u64)], window: usize) -> Option<Self> {
+        if pairs.len() < 2 {
+            return None;
+        }
+        let window = if window == 0 { pairs.len() } else { window };
+        let mut segments: Vec<ClockSegment> = Vec::new();
+        for chunk in pairs.chunks(window) {
+            let Some((scale, frib_fit)) = fit_line(chunk) else {
+                continue;
+            };
+            let get_start = chunk[0].0;
+            let frib_start = match segments.last() {
+                Some(previous) => {
+                    previous.frib_start
+                        + previous.scale * (get_start as f64 - previous.get_start as f64)
+                }
+                None => frib_fit,
+            };
+            segments.push(ClockSegment {
+                get_start,
+                frib_start,
+                scale,
+            });
+        }
+        if segments.is_empty() {
+            None
+        } else {
+            Some(Self { segments })
+        }
+    }
+
+    /// Predict the FRIB time stamp for a GET time stamp.
+    pub fn predict(&self, get_ts: u64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.get_start <= get_ts)
+            .saturating_sub(1);
+        let segment = &self.segments[index];
+        segment.frib_start + segment.scale * (get_ts as f64 - segment.get_start as f64)
+    }
+}
+
+/// Least squares fit of frib = scale * get + offset.
+/// Values are centered on the first pair to keep the precision of the large time stamps,
+/// so the fit is returned as the scale and the fitted FRIB time stamp at the first pair.
+fn fit_line(pairs: &[(u64, u64)]) -> Option<(f64, f64)> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let (x0, y0) = pairs[0];
+    let n = pairs.len() as f64;
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    let mut sum_xx = 0.0;
+    let mut sum_xy = 0.0;
+    for (get, frib) in pairs {
+        let x = *get as f64 - x0 as f64;
+        let y = *frib as f64 - y0 as f64;
+        sum_x += x;
+        sum_y += y;
+        sum_xx += x * x;
+        sum_xy += x * y;
+    }
+    let denominator = n * sum_xx - sum_x * sum_x;
+    if denominator == 0.0 {
+        return None;
+    }
+    let scale = (n * sum_xy - sum_x * sum_y) / denominator;
+    let intercept = (sum_y - scale * sum_x) / n;
+    Some((scale, y0 as f64 + intercept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_needs_two_pairs() {
+        for window in [0, 1, 10] {
+            assert_eq!(ClockModel::fit(&[], window), None);
+            assert_eq!(ClockModel::fit(&[(100, 250)], window), None);
+        }
+    }
+
+    #[test]
+    fn fit_recovers_a_line() {
+        let pairs: Vec<(u64, u64)> = (0..100).map(|i| (1000 + 10 * i, 500 + 25 * i)).collect();
+        let model = ClockModel::fit(&pairs, 0).unwrap();
+        assert_eq!(model.segments.len(), 1);
+        assert!((model.segments[0].scale - 2.5).abs() < 1e-9);
+        assert!((model.predict(1500) - 1750.0).abs() < 1e-6);
+    }
+}
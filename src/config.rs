@@ -1,16 +1,380 @@
 //! The definition of a configuration for the harmonizer
 use color_eyre::eyre::{eyre, Result};
+use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Defines a configuration. It is Ser/De-able with serde.
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub merger_path: PathBuf,
     pub sync_path: PathBuf,
-    pub min_run: i32,
-    pub max_run: i32,
+    /// First and last runs to synchronize, required unless `runs` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_run: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_run: Option<i32>,
+    /// Runs to synchronize in place of the min_run..=max_run range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runs: Option<RunSelection>,
+    /// Runs that are never synchronized
+    #[serde(default)]
+    pub exclude: Vec<i32>,
+    /// Groups of consecutive runs synchronized as a single stream, written to the output of the first run
+    #[serde(default)]
+    pub merge_runs: Vec<Vec<i32>>,
+    /// Number of runs processed at the same time
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Level of the messages printed to the console
+    #[serde(default)]
+    pub log_level: LogLevel,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+fn default_workers() -> usize {
+    1
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            merger_path: PathBuf::default(),
+            sync_path: PathBuf::default(),
+            min_run: Some(0),
+            max_run: Some(0),
+            runs: None,
+            exclude: Vec::new(),
+            merge_runs: Vec::new(),
+            workers: default_workers(),
+            log_level: LogLevel::default(),
+            sync: SyncConfig::default(),
+            output: OutputConfig::default(),
+        }
+    }
+}
+
+/// An explicit selection of runs: a single run number, a list of run numbers, or an expression
+/// of comma separated runs and inclusive ranges such as "55-60, 63, 67-69".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RunSelection {
+    Single(i32),
+    List(Vec<i32>),
+    Expression(String),
+}
+
+impl RunSelection {
+    /// The runs of the selection, in the order given.
+    pub fn runs(&self) -> Result<Vec<i32>> {
+        match self {
+            Self::Single(run) => Ok(vec![*run]),
+            Self::List(runs) => Ok(runs.clone()),
+            Self::Expression(expression) => parse_run_expression(expression),
+        }
+    }
+}
+
+/// Parse a run expression of comma separated runs and inclusive ranges, such as "55-60, 63, 67-69".
+fn parse_run_expression(expression: &str) -> Result<Vec<i32>> {
+    let mut runs = Vec::new();
+    for item in expression.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let parse = |run: &str| {
+            run.trim()
+                .parse::<i32>()
+                .map_err(|_| eyre!("Invalid run {} in run expression \"{}\"", run.trim(), expression))
+        };
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(eyre!(
+                        "Invalid run range {} in run expression \"{}\": the range is reversed",
+                        item,
+                        expression
+                    ));
+                }
+                runs.extend(first..=last);
+            }
+            None => runs.push(parse(item)?),
+        }
+    }
+    Ok(runs)
+}
+
+/// Options controlling what is written to the synchronized files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Write the events that could not be synchronized to the unmatched group
+    pub write_unmatched: bool,
+    /// Compression filter applied to the trace datasets
+    pub compression: Compression,
+    /// Compression level of the deflate filter (0-9)
+    pub deflate_level: u8,
+    /// Apply the byte shuffle filter before compressing
+    pub shuffle: bool,
+    /// Number of rows (pads) per chunk of the GET traces, 0 for one chunk per event
+    pub chunk_rows: usize,
+    /// What to do when the output of a run already exists
+    pub existing: ExistingPolicy,
+    /// Size in bytes after which the output of a run continues in a new part, 0 for no limit
+    pub max_file_size: u64,
+}
+
+/// The policies available for runs whose output already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingPolicy {
+    /// The run is reported as an error and the existing output is left untouched
+    #[default]
+    Error,
+    /// The run is skipped if the existing output is complete, otherwise it is synchronized again
+    Skip,
+    /// The existing output is replaced
+    Overwrite,
+}
+
+impl FromStr for ExistingPolicy {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            _ => Err(eyre!("Invalid policy for existing outputs: {}", s)),
+        }
+    }
+}
+
+/// The levels of the messages printed to the console, from the least to the most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// The level, made more verbose `verbose` times and less verbose `quiet` times.
+    pub fn adjusted(&self, verbose: u8, quiet: u8) -> LevelFilter {
+        const LEVELS: [LevelFilter; 6] = [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ];
+        let index = *self as i32 + verbose as i32 - quiet as i32;
+        LEVELS[index.clamp(0, LEVELS.len() as i32 - 1) as usize]
+    }
+}
+
+/// The compression filters available for the trace datasets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    /// The LZF filter built into the synchronizer, fast but less compact than deflate
+    Lzf,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            write_unmatched: false,
+            compression: Compression::None,
+            deflate_level: 4,
+            shuffle: false,
+            chunk_rows: 0,
+            existing: ExistingPolicy::Error,
+            max_file_size: 0,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Check that the options are sensible.
+    pub fn validate(&self) -> Result<()> {
+        if self.deflate_level > 9 {
+            return Err(eyre!(
+                "output.deflate_level must be between 0 and 9, found {}",
+                self.deflate_level
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parameters controlling how the GET and FRIB time stamps are synchronized.
+/// All values are in clock ticks of the time stamp differences.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Maximum difference between the first TS differences for the first events to be considered aligned
+    pub alignment_threshold: i64,
+    /// Number of consecutive TS differences used when searching for a matching pattern
+    pub pattern_depth: usize,
+    /// Maximum summed difference over the pattern for it to be considered a match
+    pub pattern_tolerance: i64,
+    /// Jitter above which a stream is considered to have lost lock (an event is missing)
+    pub mismatch_threshold: i64,
+    /// Jitter above which a warning is printed for a matched event
+    pub jitter_warning: i64,
+    /// Algorithm used to align the GET and FRIB streams
+    pub mode: AlignmentMode,
+    /// Cost of leaving one event unmatched in the global alignment
+    pub gap_penalty: i64,
+    /// Largest offset (in events) between the two streams considered by the global alignment
+    pub band: usize,
+    /// Largest number of consecutive events skipped on one stream by the global alignment
+    pub max_gap: usize,
+    /// Nominal number of FRIB clock ticks per GET clock tick
+    pub clock_ratio: f64,
+    /// Fit the relation between the GET and FRIB clocks from the matched events and synchronize again
+    pub fit_clock: bool,
+    /// Number of confidently matched events used for the clock fit
+    pub fit_events: usize,
+    /// If non-zero, the clock fit is done piecewise every drift_window matched events to track drift
+    pub drift_window: usize,
+    /// Width in bits of the FRIB time stamp counter, used to unwrap it when it rolls over
+    pub frib_timestamp_bits: u32,
+    /// Runs with fewer GET or FRIB events than this are handled by the short_run policy
+    pub min_events: usize,
+    /// What to do with runs that are too short to synchronize
+    pub short_run: ShortRunPolicy,
+}
+
+/// The policies available for runs that are too short to synchronize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShortRunPolicy {
+    /// The run is reported as an error and not written
+    #[default]
+    Error,
+    /// The GET and FRIB events are paired in order without any alignment
+    Trivial,
+}
+
+/// The algorithms available to align the GET and FRIB streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AlignmentMode {
+    /// Find the first aligned events with a pattern search and then walk both streams
+    #[default]
+    Greedy,
+    /// Dynamic-programming sequence alignment with gap penalties over the whole run
+    Global,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            alignment_threshold: 100,
+            pattern_depth: 5,
+            pattern_tolerance: 5,
+            mismatch_threshold: 1000,
+            jitter_warning: 5,
+            mode: AlignmentMode::Greedy,
+            gap_penalty: 1000,
+            band: 100,
+            max_gap: 3,
+            clock_ratio: 1.0,
+            fit_clock: false,
+            fit_events: 1000,
+            drift_window: 0,
+            frib_timestamp_bits: 32,
+            min_events: 10,
+            short_run: ShortRunPolicy::Error,
+        }
+    }
+}
+
+impl SyncConfig {
+    /// Check that the parameters are sensible.
+    pub fn validate(&self) -> Result<()> {
+        if self.pattern_depth < 2 {
+            return Err(eyre!(
+                "sync.pattern_depth must be at least 2, found {}",
+                self.pattern_depth
+            ));
+        }
+        if self.alignment_threshold <= 0
+            || self.mismatch_threshold <= 0
+            || self.jitter_warning <= 0
+        {
+            return Err(eyre!(
+                "sync.alignment_threshold, sync.mismatch_threshold and sync.jitter_warning must be positive"
+            ));
+        }
+        if self.pattern_tolerance < 0 {
+            return Err(eyre!(
+                "sync.pattern_tolerance must not be negative, found {}",
+                self.pattern_tolerance
+            ));
+        }
+        if self.jitter_warning > self.mismatch_threshold {
+            return Err(eyre!(
+                "sync.jitter_warning ({}) must not exceed sync.mismatch_threshold ({})",
+                self.jitter_warning,
+                self.mismatch_threshold
+            ));
+        }
+        if self.gap_penalty <= 0 {
+            return Err(eyre!(
+                "sync.gap_penalty must be positive, found {}",
+                self.gap_penalty
+            ));
+        }
+        if self.band == 0 {
+            return Err(eyre!("sync.band must be at least 1"));
+        }
+        if self.max_gap >= u8::MAX as usize {
+            return Err(eyre!(
+                "sync.max_gap must be less than {}, found {}",
+                u8::MAX,
+                self.max_gap
+            ));
+        }
+        if !(self.clock_ratio.is_finite() && self.clock_ratio > 0.0) {
+            return Err(eyre!(
+                "sync.clock_ratio must be a positive number, found {}",
+                self.clock_ratio
+            ));
+        }
+        if self.fit_clock && (self.fit_events < 2 || self.drift_window == 1) {
+            return Err(eyre!(
+                "sync.fit_events must be at least 2 and sync.drift_window must not be 1 to fit the clocks"
+            ));
+        }
+        if self.frib_timestamp_bits == 0 || self.frib_timestamp_bits > 32 {
+            return Err(eyre!(
+                "sync.frib_timestamp_bits must be between 1 and 32, found {}",
+                self.frib_timestamp_bits
+            ));
+        }
+        if self.min_events < 2 {
+            return Err(eyre!(
+                "sync.min_events must be at least 2, found {}",
+                self.min_events
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Config {
@@ -24,7 +388,99 @@ impl Config {
         }
 
         let yaml_str = std::fs::read_to_string(path)?;
-        Ok(serde_yaml::from_str::<Self>(&yaml_str)?)
+        let config = serde_yaml::from_str::<Self>(&yaml_str)?;
+        let runs = config.run_list()?;
+        config.validate_merge_runs(&runs)?;
+        if config.workers == 0 {
+            return Err(eyre!("Invalid workers: at least one worker is required"));
+        }
+        config.sync.validate()?;
+        config.output.validate()?;
+        Ok(config)
+    }
+
+    /// The runs to synchronize, in increasing order: the `runs` selection if given,
+    /// otherwise the min_run..=max_run range, without the excluded runs.
+    pub fn run_list(&self) -> Result<Vec<i32>> {
+        let mut runs = match &self.runs {
+            Some(selection) => {
+                let runs = selection.runs()?;
+                if runs.is_empty() {
+                    return Err(eyre!("Invalid runs: the selection is empty"));
+                }
+                runs
+            }
+            None => match (self.min_run, self.max_run) {
+                (Some(min_run), Some(max_run)) => (min_run..=max_run).collect(),
+                _ => {
+                    return Err(eyre!(
+                        "Invalid runs: either runs or both min_run and max_run must be given"
+                    ))
+                }
+            },
+        };
+        runs.sort_unstable();
+        runs.dedup();
+        runs.retain(|run| !self.exclude.contains(run));
+        Ok(runs)
+    }
+
+    /// Check that each group of merged runs is a non-empty list of consecutive runs
+    /// and that no run belongs to more than one group. A group is synchronized as a whole,
+    /// so its runs must be either all selected in `runs` or none of them, and none excluded.
+    fn validate_merge_runs(&self, runs: &[i32]) -> Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for group in &self.merge_runs {
+            if group.is_empty() {
+                return Err(eyre!("Invalid merge_runs: a group of runs is empty"));
+            }
+            if group.windows(2).any(|pair| pair[1] != pair[0] + 1) {
+                return Err(eyre!(
+                    "Invalid merge_runs: runs {:?} are not consecutive",
+                    group
+                ));
+            }
+            for run in group {
+                if !seen.insert(*run) {
+                    return Err(eyre!(
+                        "Invalid merge_runs: run {} is in more than one group",
+                        run
+                    ));
+                }
+                if self.exclude.contains(run) {
+                    return Err(eyre!(
+                        "Invalid merge_runs: run {} of {:?} is excluded",
+                        run,
+                        group
+                    ));
+                }
+            }
+            let selected = group.iter().filter(|run| runs.contains(run)).count();
+            if selected != 0 && selected != group.len() {
+                return Err(eyre!(
+                    "Invalid merge_runs: only some of the runs {:?} are selected",
+                    group
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The runs synchronized together with the given run, starting with the run itself.
+    /// Runs that are not merged are synchronized on their own.
+    pub fn runs_starting_at(&self, run: i32) -> Vec<i32> {
+        self.merge_runs
+            .iter()
+            .find(|group| group[0] == run)
+            .cloned()
+            .unwrap_or_else(|| vec![run])
+    }
+
+    /// Whether the run is merged into the stream of a previous run.
+    pub fn is_merged_continuation(&self, run: i32) -> bool {
+        self.merge_runs
+            .iter()
+            .any(|group| group[1..].contains(&run))
     }
 
     /// Save this configuration to a YAML file.
@@ -35,3 +491,77 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_run_expressions() {
+        let cases: [(&str, &[i32]); 6] = [
+            ("55", &[55]),
+            ("55-58", &[55, 56, 57, 58]),
+            ("55-56, 63, 67-68", &[55, 56, 63, 67, 68]),
+            (" 60 - 61 ,55", &[60, 61, 55]),
+            ("55,,56,", &[55, 56]),
+            ("57-57", &[57]),
+        ];
+        for (expression, runs) in cases {
+            assert_eq!(parse_run_expression(expression).unwrap(), runs, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_run_expressions() {
+        for expression in ["60-55", "abc", "55-", "-55", "55-60-65", "55; 56", "5.5"] {
+            assert!(parse_run_expression(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn deserializes_run_selections() {
+        let cases: [(&str, &[i32]); 4] = [
+            ("55", &[55]),
+            ("[55, 57, 56]", &[55, 57, 56]),
+            ("\"55-57, 60\"", &[55, 56, 57, 60]),
+            ("[]", &[]),
+        ];
+        for (yaml, runs) in cases {
+            let selection: RunSelection = serde_yaml::from_str(yaml).unwrap();
+            assert_eq!(selection.runs().unwrap(), runs, "{}", yaml);
+        }
+        for yaml in ["{first: 55}", "[55, a]", "5.5"] {
+            assert!(serde_yaml::from_str::<RunSelection>(yaml).is_err(), "{}", yaml);
+        }
+        let selection: RunSelection = serde_yaml::from_str("\"60-55\"").unwrap();
+        assert!(selection.runs().is_err());
+    }
+
+    #[test]
+    fn adjusts_the_log_level() {
+        let cases = [
+            (LogLevel::Info, 0, 0, LevelFilter::Info),
+            (LogLevel::Info, 1, 0, LevelFilter::Debug),
+            (LogLevel::Info, 2, 0, LevelFilter::Trace),
+            (LogLevel::Info, 5, 0, LevelFilter::Trace),
+            (LogLevel::Info, 0, 1, LevelFilter::Warn),
+            (LogLevel::Info, 0, 3, LevelFilter::Off),
+            (LogLevel::Info, 0, 10, LevelFilter::Off),
+            (LogLevel::Warn, 1, 1, LevelFilter::Warn),
+            (LogLevel::Off, 1, 0, LevelFilter::Error),
+            (LogLevel::Trace, 0, 1, LevelFilter::Debug),
+            (LogLevel::Debug, u8::MAX, 0, LevelFilter::Trace),
+            (LogLevel::Error, 0, u8::MAX, LevelFilter::Off),
+        ];
+        for (level, verbose, quiet, expected) in cases {
+            assert_eq!(
+                level.adjusted(verbose, quiet),
+                expected,
+                "{:?} -v x{} -q x{}",
+                level,
+                verbose,
+                quiet
+            );
+        }
+    }
+}
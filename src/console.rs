@@ -0,0 +1,131 @@
+//! Logging of the synchronizer. Messages are printed to the console at the configured level.
+//! When several runs are processed at the same time, each line is prefixed with the run it
+//! belongs to so that the output stays readable. When stdout is a terminal, lines are printed
+//! above the progress bars. The full log of each run (down to the debug level) is also
+//! written to its log file.
+use color_eyre::eyre::Result;
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+thread_local! {
+    static RUN_CONTEXT: Cell<Option<i32>> = const { Cell::new(None) };
+    static RUN_LOG: RefCell<Option<BufWriter<File>>> = const { RefCell::new(None) };
+}
+
+static PROGRESS_BARS: OnceLock<MultiProgress> = OnceLock::new();
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+/// Level of the messages written to the run log files
+const RUN_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Construct the path of the log file of a run from a parent path and run number.
+pub fn construct_log_path(path: &Path, run_number: i32) -> PathBuf {
+    path.join(format!("run_{:0>4}.log", run_number))
+}
+
+/// The logger, writing to the console and to the log file of the run of the current thread.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() as usize <= CONSOLE_LEVEL.load(Ordering::Relaxed) {
+            let line = match record.level() {
+                Level::Info => format!("{}{}", run_prefix(), record.args()),
+                level => format!("{}{}: {}", run_prefix(), level, record.args()),
+            };
+            print_line(&line);
+        }
+        if record.level() <= RUN_LOG_LEVEL {
+            RUN_LOG.with(|run_log| {
+                if let Some(writer) = run_log.borrow_mut().as_mut() {
+                    let _ = writeln!(writer, "{:<5} {}", record.level(), record.args());
+                }
+            });
+        }
+    }
+
+    fn flush(&self) {
+        RUN_LOG.with(|run_log| {
+            if let Some(writer) = run_log.borrow_mut().as_mut() {
+                let _ = writer.flush();
+            }
+        });
+    }
+}
+
+/// Install the logger, printing messages up to the given level to the console.
+pub fn init_logging(level: LevelFilter) {
+    static LOGGER: Logger = Logger;
+    let _ = log::set_logger(&LOGGER);
+    set_console_level(level);
+}
+
+/// Change the level of the messages printed to the console.
+pub fn set_console_level(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+    log::set_max_level(level.max(RUN_LOG_LEVEL));
+}
+
+/// Start writing the log of the current thread to a run log file. Messages are appended,
+/// so the file keeps the history of every time the run was processed.
+pub fn start_run_log(path: &Path) -> Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    RUN_LOG.with(|run_log| *run_log.borrow_mut() = Some(BufWriter::new(file)));
+    Ok(())
+}
+
+/// Stop writing the log of the current thread to its run log file.
+pub fn finish_run_log() {
+    RUN_LOG.with(|run_log| {
+        if let Some(mut writer) = run_log.borrow_mut().take() {
+            let _ = writer.flush();
+        }
+    });
+}
+
+/// Set the run whose output is printed by the current thread, None for no prefix.
+pub fn set_run_context(run: Option<i32>) {
+    RUN_CONTEXT.with(|context| context.set(run));
+}
+
+/// The prefix of the lines printed by the current thread.
+fn run_prefix() -> String {
+    match RUN_CONTEXT.with(|context| context.get()) {
+        Some(run) => format!("[run {:0>4}] ", run),
+        None => String::new(),
+    }
+}
+
+/// Enable the progress bars if stdout is a terminal. Otherwise progress is
+/// reported with periodic lines.
+pub fn enable_progress_bars() {
+    if std::io::stdout().is_terminal() {
+        let _ = PROGRESS_BARS.set(MultiProgress::with_draw_target(ProgressDrawTarget::stdout()));
+    }
+}
+
+/// The progress bars, if they are enabled.
+pub fn progress_bars() -> Option<&'static MultiProgress> {
+    PROGRESS_BARS.get()
+}
+
+/// Print a line, keeping it above the progress bars if they are enabled.
+/// The whole line is printed at once, so lines from different runs don't interleave.
+fn print_line(line: &str) {
+    match progress_bars() {
+        Some(bars) => bars.suspend(|| println!("{}", line)),
+        None => println!("{}", line),
+    }
+}
@@ -0,0 +1,203 @@
+//! Inspection of merger and synchronizer files: format, events, scalers and sync metadata.
+use crate::config::SyncConfig;
+use crate::reader::{MergerReader, MergerVersion};
+use color_eyre::eyre::Result;
+use hdf5_metno::types::VarLenUnicode;
+use hdf5_metno::{File, Group};
+use human_bytes::human_bytes;
+use std::path::Path;
+
+/// Print a description of a merger (0.1.0 or 0.2.0) or synchronizer file.
+pub fn inspect(path: &Path) -> Result<()> {
+    let mut reader = MergerReader::open_file(path)?;
+    let version = reader.version().clone();
+    println!("File: {}", path.display());
+    match version {
+        MergerVersion::Synchronizer => {
+            let events_version = reader
+                .current_file
+                .group("events")?
+                .attr("version")?
+                .read_scalar::<VarLenUnicode>()?;
+            println!("Format: synchronizer output ({})", events_version.as_str());
+        }
+        _ => println!("Format: merger {}", version.as_str()),
+    }
+    let (min_event, max_event) = reader.event_range()?;
+    println!(
+        "Events: {} to {} ({} events)",
+        min_event,
+        max_event.saturating_sub(1),
+        max_event.saturating_sub(min_event)
+    );
+
+    // The time stamp scan tells which side each event has
+    reader.read_ts(&SyncConfig::default())?;
+    let both = reader.get_events.len() - reader.orphan_get;
+    println!(
+        "Event types: {} GET and FRIB, {} GET-only, {} FRIB-only, {} empty",
+        both, reader.orphan_get, reader.orphan_frib, reader.empty_events
+    );
+
+    let file = &reader.current_file;
+    print_scalers(file, &version)?;
+    print_shapes(
+        file,
+        &version,
+        reader.get_events.first().copied(),
+        reader.frib_events.first().copied(),
+    )?;
+    if version == MergerVersion::Synchronizer {
+        print_unmatched(file)?;
+        print_sync_meta(file)?;
+    }
+    Ok(())
+}
+
+/// Print the range of scalers in the file.
+fn print_scalers(file: &File, version: &MergerVersion) -> Result<()> {
+    let range = match version {
+        MergerVersion::V010 => {
+            let count = file
+                .group("frib")?
+                .group("scaler")?
+                .member_names()?
+                .iter()
+                .filter(|name| name.starts_with("scaler") && name.ends_with("_data"))
+                .count() as u32;
+            (count > 0).then(|| (0, count - 1))
+        }
+        _ => match file.group("scalers") {
+            Ok(group) => Some((
+                group.attr("min_event")?.read_scalar::<u32>()?,
+                group.attr("max_event")?.read_scalar::<u32>()?,
+            )),
+            Err(_) => None,
+        },
+    };
+    match range {
+        Some((min, max)) => println!("Scalers: {} to {}", min, max),
+        None => println!("Scalers: none"),
+    }
+    Ok(())
+}
+
+/// Print the shapes of the trace datasets of the first GET and FRIB events.
+fn print_shapes(
+    file: &File,
+    version: &MergerVersion,
+    get_event: Option<u64>,
+    frib_event: Option<u64>,
+) -> Result<()> {
+    if let Some(event) = get_event {
+        let dataset = match version {
+            MergerVersion::V010 => file.group("get")?.dataset(&format!("evt{}_data", event))?,
+            _ => file
+                .group("events")?
+                .group(&format!("event_{}", event))?
+                .dataset("get_traces")?,
+        };
+        println!("GET traces (event {}): {:?}", event, dataset.shape());
+    }
+    if let Some(event) = frib_event {
+        let (dataset_977, dataset_1903) = match version {
+            MergerVersion::V010 => {
+                let group = file.group("frib")?.group("evt")?;
+                (
+                    group.dataset(&format!("evt{}_977", event))?,
+                    group.dataset(&format!("evt{}_1903", event))?,
+                )
+            }
+            _ => {
+                let group = file
+                    .group("events")?
+                    .group(&format!("event_{}", event))?
+                    .group("frib_physics")?;
+                (group.dataset("977")?, group.dataset("1903")?)
+            }
+        };
+        println!(
+            "FRIB 977 (event {}): {:?}, FRIB 1903: {:?}",
+            event,
+            dataset_977.shape(),
+            dataset_1903.shape()
+        );
+    }
+    Ok(())
+}
+
+/// Print the number of unmatched events kept in a synchronizer output.
+fn print_unmatched(file: &File) -> Result<()> {
+    let Ok(unmatched) = file.group("unmatched") else {
+        return Ok(());
+    };
+    let count = |side: &str| -> Result<usize> {
+        match unmatched.group(side) {
+            Ok(group) => Ok(group.member_names()?.len()),
+            Err(_) => Ok(0),
+        }
+    };
+    println!(
+        "Unmatched events: {} GET, {} FRIB",
+        count("get")?,
+        count("frib")?
+    );
+    Ok(())
+}
+
+/// Print the sync metadata of a synchronizer output.
+fn print_sync_meta(file: &File) -> Result<()> {
+    let Ok(meta) = file.group("sync_meta") else {
+        println!("No sync_meta (only the first part of an output has it)");
+        return Ok(());
+    };
+    let string = |group: &Group, name: &str| -> Result<String> {
+        Ok(group.attr(name)?.read_scalar::<VarLenUnicode>()?.to_string())
+    };
+    println!("Sync metadata:");
+    println!("  Input: {}", string(&meta, "input_path")?);
+    println!(
+        "  Input size: {}",
+        human_bytes(meta.attr("input_size")?.read_scalar::<u64>()? as f64)
+    );
+    if let Ok(runs) = meta.dataset("input_runs") {
+        println!("  Input runs: {:?}", runs.read_raw::<i32>()?);
+    }
+    println!("  Merger version: {}", string(&meta, "merger_version")?);
+    if let Ok(versions) = meta.dataset("merger_versions") {
+        let versions: Vec<String> = versions
+            .read_raw::<VarLenUnicode>()?
+            .iter()
+            .map(|version| version.to_string())
+            .collect();
+        println!("  Merger versions of the runs: {}", versions.join(", "));
+    }
+    println!(
+        "  First aligned events: GET {}, FRIB {}",
+        meta.attr("first_get_event")?.read_scalar::<i64>()?,
+        meta.attr("first_frib_event")?.read_scalar::<i64>()?
+    );
+    println!(
+        "  Mismatches: {}",
+        meta.attr("mismatches")?.read_scalar::<u64>()?
+    );
+    println!(
+        "  FRIB wraparounds: {}",
+        meta.attr("frib_wraps")?.read_scalar::<u64>()?
+    );
+    let get_start = meta.dataset("clock_get_start")?.read_raw::<u64>()?;
+    let frib_start = meta.dataset("clock_frib_start")?.read_raw::<f64>()?;
+    let scale = meta.dataset("clock_scale")?.read_raw::<f64>()?;
+    println!("  Clock model:");
+    for ((get_start, frib_start), scale) in get_start.iter().zip(&frib_start).zip(&scale) {
+        println!(
+            "    from GET {}: FRIB = {} + {} * (GET - {})",
+            get_start, frib_start, scale, get_start
+        );
+    }
+    println!("  Configuration:");
+    for line in string(&meta, "config")?.lines() {
+        println!("    {}", line);
+    }
+    Ok(())
+}
@@ -40,6 +40,30 @@
 //! synchronizer --config/-c /path/to/some/config.yml new
 //! ```
 //!
+//! To check the alignment of new runs quickly, the `dry-run` command reads the time stamps and aligns each run, reporting the first aligned events, the mismatches and the jitter statistics (mean, standard deviation, maximum and number of events above `jitter_warning`) without writing any synchronized file. With `--sync-map`, the sync map of each run is written to the `sync_path`.
+//!
+//! ```txt
+//! synchronizer --config/-c /path/to/some/config.yml dry-run [--sync-map]
+//! ```
+//!
+//! The `inspect` command describes a merger (0.1.0 or 0.2.0) or synchronizer file: its format, the range of events, how many events have both GET and FRIB data or only one of them, the range of scalers and the shapes of the trace datasets. For synchronizer outputs, the number of unmatched events and the `sync_meta` metadata are printed as well. It does not need a configuration.
+//!
+//! ```txt
+//! synchronizer inspect /path/to/some/run_0001.h5
+//! ```
+//!
+//! The `verify` command checks a synchronized file (and its other parts) against the merger files it was made from, using the configuration stored in its `sync_meta`. Every event is read again and its GET and FRIB data must be identical to the source events given by `orig_run`, `orig_get_event`, `orig_frib_run` and `orig_frib_event`. The jitter between consecutive events, with the GET time stamps converted by the stored clock model, must be within `jitter_warning`; the threshold used is printed. The `min_event`/`max_event` range of each part must match its events, and the number of scalers must match the merger files. Each discrepancy is printed, and the synchronizer exits with a non-zero code if any was found.
+//!
+//! ```txt
+//! synchronizer verify /path/to/some/run_0001.h5
+//! ```
+//!
+//! Each run is processed on its own: if a run fails, its partially written output is removed and the synchronizer moves on to the next run. Once all runs are processed, a table with the status, the number of GET and FRIB events, the number of GET-only, FRIB-only and empty merger events, and the number of matched, dropped and mismatched events of each run is printed. The synchronizer exits with a non-zero code if any run failed.
+//!
+//! Output files are first written to a temporary `run_XXXX.h5.tmp` file in the `sync_path`, which is renamed to `run_XXXX.h5` only once it is complete. The sync map is likewise written to `run_XXXX_sync.parquet.tmp` and moved into place together with the output, so a run never leaves an output without its sync map. An interrupted synchronization therefore never leaves an incomplete `run_XXXX.h5` behind; the stale temporary files of a run are removed the next time that run is synchronized. Only the runs being synchronized are touched, so several synchronizers can work on different runs of the same `sync_path`.
+//!
+//! When run in a terminal, the synchronizer shows progress bars for the runs and, for each run, for the time stamp scan and the writing of the synchronized file, with the rate of events and of data (input data for the scan, trace data for the writing). When the output is not a terminal (e.g. redirected to a log file), the progress is printed as a line every 10 seconds instead. Each pass ends with a line giving its duration and average rates.
+//!
 //! ### Configuration
 //!
 //! Configurations are defined as the following YAML:
@@ -49,12 +73,53 @@
 //! sync_path: "/path/to/some/synchronic/data/"
 //! min_run: 55
 //! max_run: 69
+//! exclude: []
+//! merge_runs: []
+//! workers: 1
+//! log_level: info
+//! sync:
+//!   alignment_threshold: 100
+//!   pattern_depth: 5
+//!   pattern_tolerance: 5
+//!   mismatch_threshold: 1000
+//!   jitter_warning: 5
+//!   mode: greedy
+//!   gap_penalty: 1000
+//!   band: 100
+//!   max_gap: 3
+//!   clock_ratio: 1.0
+//!   fit_clock: false
+//!   fit_events: 1000
+//!   drift_window: 0
+//!   frib_timestamp_bits: 32
+//!   min_events: 10
+//!   short_run: error
+//! output:
+//!   write_unmatched: false
+//!   compression: none
+//!   deflate_level: 4
+//!   shuffle: false
+//!   chunk_rows: 0
+//!   existing: error
+//!   max_file_size: 0
 //! ```
 //!
 //! Some important notes:
 //!
 //! - The path given as the `sync_path` must exist before running the synchronizer
 //! - Min run and max run are the range of run numbers (*merger run numbers*) to be synchronized. The range is inclusive; run numbers can be missing in the range.
+//! - Instead of the range, the runs to synchronize can be given with the optional `runs`, either as a single run (`runs: 55`), a list (`runs: [55, 56, 63]`) or as an expression of runs and inclusive ranges (`runs: "55-60, 63, 67-69"`). When `runs` is given, `min_run` and `max_run` can be left out and are ignored; otherwise both are required. The runs in `exclude` (e.g. known bad runs) are never synchronized. An invalid `runs` expression is reported when the configuration is loaded.
+//! - `merge_runs` is an optional list of groups of consecutive runs, e.g. `[[57, 58, 59]]`, that were split by the DAQ but belong together. The runs of a group are read as a single stream of time stamps and synchronized together, and the output is written to the file of the first run of the group. A run can only be in one group, and all runs of a group must exist. The runs of a group must be either all selected or none of them, and none of them can be excluded; such configurations are rejected when loaded. Unmatched events from the other runs of a group are named `event_<run>_<event>`.
+//! - `workers` is the number of runs processed at the same time (also `-w/--workers` on the command line). The HDF5 library is only called by one worker at a time, so the speed-up comes from aligning one run while others are read or written. With more than one worker, each line of output is prefixed with its run, e.g. `[run 0055]`, and the summary is still ordered by run number.
+//! - `log_level` is the level of the messages printed: `off`, `error`, `warn`, `info`, `debug` or `trace`. Each `-v/--verbose` on the command line makes it one level more verbose, and each `-q/--quiet` one level less. Whatever the level printed, the full log of each run (down to the `debug` level, including the dropped events and every abnormal jitter) is appended to `run_XXXX.log` in the `sync_path`.
+//! - The `sync` section is optional; the values shown are the defaults. All values are in time stamp ticks. `alignment_threshold` decides whether the first events are already aligned, `pattern_depth` and `pattern_tolerance` control the search for the first aligned events, a jitter above `mismatch_threshold` (in either direction) is treated as an extra event on the stream that got its next event earlier, which is dropped, and the events with a jitter above `jitter_warning` are counted and reported (each of them is listed in the debug log).
+//! - The `mode` of the alignment is either `greedy` (pattern search for the first aligned events, then both streams are walked in step) or `global` (a dynamic-programming sequence alignment over the whole run, which can recover from events missing on either stream). In `global` mode every unmatched event costs `gap_penalty`, each GET event is only matched within `band` events of the match of the previous GET event (the band follows the alignment, so the offset between the streams can grow over the run) and at most `max_gap` consecutive events can be skipped on one stream. The alignment keeps about 2 × (2 × `band` + 1) bytes per GET event (about 400 MB for a million events with the default `band`); lower `band` for very long runs.
+//! - The GET time stamps are converted to the FRIB clock before they are compared, using `clock_ratio` FRIB ticks per GET tick. With `fit_clock` enabled, a linear relation between the clocks is fit to the first `fit_events` confidently matched events and the run is synchronized again using the predicted time stamps. A non-zero `drift_window` fits the relation piecewise every `drift_window` matched events to follow a slow drift.
+//! - `frib_timestamp_bits` is the width of the FRIB time stamp counter. The FRIB time stamps are unwrapped every time the counter rolls over and the number of wraparounds is reported for each run.
+//! - Runs with fewer than `min_events` GET or FRIB events are too short to be aligned. With `short_run: error` such a run is reported and skipped; with `short_run: trivial` its GET and FRIB events are paired in order.
+//! - The `output` section is optional as well. With `write_unmatched` enabled, the events that could not be synchronized are kept in the `unmatched` group of the output, under their original event numbers. The trace datasets can be compressed with `compression` set to `deflate` (at `deflate_level` 0-9) or `lzf` (the LZF filter is built into the synchronizer), optionally preceded by the byte `shuffle` filter. Compressed datasets are chunked: `chunk_rows` sets the number of pads per chunk of the GET traces, with 0 meaning one chunk per event. The filters used are recorded in the `filters` and `chunk_rows` attributes of the `events` group.
+//! - `existing` decides what happens to runs whose output already exists in the `sync_path`: `error` reports the run as failed and leaves the output untouched, `skip` skips the run if its output is complete (written by this version of the synchronizer, with its `sync_meta`, and every part holding all of its events from `min_event` to `max_event`, continuing where the previous part ended), and `overwrite` replaces the output. It can be overridden from the command line with `--existing error|skip|overwrite`.
+//! - `max_file_size` (in bytes) splits large outputs: once a file reaches this size, the run continues in `run_XXXX_part1.h5`, `run_XXXX_part2.h5`, etc. A value of 0 disables splitting.
 //!
 //! ### Output Format
 //!
@@ -62,73 +127,326 @@
 //!
 //! ```txt
 //! run_0001.h5
-//! |---- events - min_event, max_event, version
-//! |    |---- event_# - orig_run, orig_event
+//! |---- events - min_event, max_event, version, filters, chunk_rows
+//! |    |---- event_# - orig_run, orig_get_event, orig_frib_event, orig_frib_run
 //! |    |    |---- get_traces(dset) - id, timestamp, timestamp_other
 //! |    |    |---- frib_physics - id, timestamp
 //! |    |    |    |---- 977(dset)
 //! |    |    |    |---- 1903(dset)
+//! |---- unmatched
+//! |    |---- get
+//! |    |    |---- event_# - orig_run
+//! |    |    |    |---- get_traces(dset) - id, timestamp, timestamp_other
+//! |    |---- frib
+//! |    |    |---- event_# - orig_run
+//! |    |    |    |---- frib_physics - id, timestamp
+//! |---- scalers - min_event, max_event
+//! |    |---- event_#(dset) - start_offset, stop_offset, timestamp, incremental
+//! |---- sync_meta - config, input_path, input_size, merger_version, first_get_event, first_frib_event, mismatches, frib_wraps
+//! |    |---- input_runs(dset), merger_versions(dset), clock_get_start(dset), clock_frib_start(dset), clock_scale(dset)
 //! ```
 //!
+//! When an output is split into parts, event numbers continue across the parts: the `min_event` and `max_event` attributes of each part give its range (`max_event` is exclusive). The `scalers` and `sync_meta` groups are always in the first part, `run_XXXX.h5`, and the `unmatched` group is in the last part.
+//!
+//! Each synchronized event is stitched together from two merger events: `orig_run` and `orig_get_event` are the merger run and event holding the GET data, and `orig_frib_run` and `orig_frib_event` the merger run and event holding the FRIB data. The two runs only differ for merged runs.
+//!
+//! The `sync_meta` group makes each output self-describing: `config` is the full configuration (YAML) used, `input_path`, `input_size` and `merger_version` describe the merger file (for merged runs, the path and merger version of the first file and the total size of all files, with the runs listed in `input_runs` and the merger version of each run in `merger_versions`), `first_get_event` and `first_frib_event` are the first aligned merger events, `mismatches` is the number of events dropped within the alignment and `frib_wraps` the number of FRIB time stamp wraparounds. The `clock_*` datasets hold the pieces of the clock model used to convert GET time stamps to the FRIB clock.
+//!
+//! Alongside each output file, the synchronizer writes a sync map `run_XXXX_sync.parquet` to the `sync_path`. It has one row per output event and one row per dropped event, with the columns:
+//!
+//! - `output_event`: the event number in the output file (null for dropped events)
+//! - `get_run`, `frib_run`: the source merger runs
+//! - `get_event`, `frib_event`: the source event numbers in the merger file
+//! - `get_timestamp`, `frib_timestamp`: the raw time stamps of both sides
+//! - `get_timestamp_difference`, `frib_timestamp_difference`: the difference to the previous time stamp of the same stream
+//! - `jitter`: the residual jitter with the previous matched event, in FRIB ticks
+//! - `matched`: whether the event was matched or dropped
+//!
 //! ## Why would you do this to me?
 //!
 //! Because due to some gremlins roaming around the hardware during the experiment, the GET and FRIB DAQs didn't have the same busy and/or trigger!
 //! Or something was very wrong with the VMUSB!
+mod alignment;
+mod clock;
 mod config;
+mod console;
+mod inspect;
+mod progress;
 mod reader;
 mod scalers;
+mod summary;
+mod sync_map;
+mod verify;
 mod writer;
 
-use clap::{Arg, Command};
-use color_eyre::eyre::Result;
-use config::Config;
-// use human_bytes::human_bytes;
-// use indicatif::{ProgressBar, ProgressStyle};
-// use reader::{get_total_merger_bytes, get_total_merger_events, MergerReader};
-use reader::{MergerReader};
+use clap::{Arg, ArgAction, Command};
+use color_eyre::eyre::{eyre, Result};
+use config::{Config, ExistingPolicy, LogLevel};
+use inspect::inspect;
+use progress::Progress;
+use reader::{get_total_merger_bytes, FribEvent, GetEvent, MergerReader};
 // use scalers::process_scalers;
 use std::path::PathBuf;
-use writer::SyncWriter;
-use scalers::{copy_scalers_010, copy_scalers_020};
+use std::sync::Mutex;
+use console::{
+    construct_log_path, enable_progress_bars, finish_run_log, init_logging, set_console_level,
+    set_run_context, start_run_log,
+};
+use log::{error, info, warn};
+use writer::{is_complete_output, remove_stale_temporaries, SyncWriter};
+use scalers::copy_scalers;
 use crate::reader::construct_run_path;
+use summary::{print_summary, JitterStatistics, RunStatus, RunSummary};
+use sync_map::{construct_sync_map_path, write_sync_map};
+use verify::verify;
 
 /// Main processing loop. Takes the config and synchronizes the data for each run.
-pub fn synchronize(config: Config) -> Result<()> {
-    for run in config.min_run..=config.max_run {
+/// Each run is processed in isolation; a failed run does not stop the others.
+/// With more than one worker, runs are processed concurrently and the summaries are
+/// returned ordered by run number.
+pub fn synchronize(config: Config) -> Result<Vec<RunSummary>> {
+    let groups = run_groups(&config)?;
+
+    let progress = Mutex::new(Progress::new("Runs", "runs", groups.len() as u64));
+    let process = |runs: &[i32]| {
+        let summary = process_runs(&config, runs);
+        let bytes = get_total_merger_bytes(&config.merger_path, runs).unwrap_or(0);
+        progress.lock().expect("Run progress poisoned").inc(1, bytes);
+        summary
+    };
+    let workers = config.workers.min(groups.len());
+    if workers <= 1 {
+        let summaries = groups.iter().map(|runs| process(runs)).collect();
+        progress.into_inner().expect("Run progress poisoned").finish();
+        return Ok(summaries);
+    }
+    // The HDF5 library is not reentrant: hdf5-metno serializes all calls to it behind a
+    // global lock, so the workers overlap the alignment of one run with the I/O of another.
+    info!("Processing {} runs with {} workers...", groups.len(), workers);
+    let queue = Mutex::new(groups.iter());
+    let summaries = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some(runs) = queue.lock().expect("Run queue poisoned").next() else {
+                    break;
+                };
+                set_run_context(Some(runs[0]));
+                let summary = process(runs);
+                set_run_context(None);
+                summaries.lock().expect("Run summaries poisoned").push(summary);
+            });
+        }
+    });
+    progress.into_inner().expect("Run progress poisoned").finish();
+    let mut summaries = summaries.into_inner().expect("Run summaries poisoned");
+    summaries.sort_by_key(|summary| summary.run);
+    Ok(summaries)
+}
+
+/// The runs to process, each with the runs merged into it. Runs whose merger file is missing are skipped.
+fn run_groups(config: &Config) -> Result<Vec<Vec<i32>>> {
+    let mut groups = Vec::new();
+    for run in config.run_list()? {
+        if config.is_merged_continuation(run) {
+            continue;
+        }
         let path = construct_run_path(&config.merger_path, run);
         if !path.exists() {
-            println!("Run {} doesn't exist, skipping...", run);
+            warn!("Run {} doesn't exist, skipping...", run);
             continue;
         }
-        let mut reader = MergerReader::new(&config.merger_path, run)?;
-        println!("Processing run {}...", &run);
-        let mut writer = SyncWriter::new(&config.sync_path, run)?;
-        // First read all data and create TS lists
-        println!("Reading time stamps...");
-        reader.read_ts()?;
-        // Synchronize TS
-        println!("Synchronizing time stamps...");
-        reader.sync_ts();
-        // Write synchronized run using lists
-        println!("Writing synchronized file...");
-        for i in 0..reader.get_sync.len() {
-            reader.current_event = reader.get_sync[i] as u64;
-            let get_event = reader.read_event()?;
-            reader.current_event = reader.frib_sync[i] as u64;
-            let frib_event = reader.read_event()?;
-            writer.write_combined(get_event.expect("GET event not found"), frib_event.expect("FRIB event not found"))?;
+        groups.push(config.runs_starting_at(run));
+    }
+    Ok(groups)
+}
+
+/// Compute the alignment of each run without writing the synchronized files,
+/// reporting the jitter statistics. If `write_map` is set, the sync map of each run is written.
+pub fn dry_run(config: &Config, write_map: bool) -> Result<Vec<RunSummary>> {
+    let mut summaries = Vec::new();
+    for runs in run_groups(config)? {
+        let run = runs[0];
+        info!("Checking run {}...", run);
+        let summary = match dry_run_runs(config, &runs, write_map) {
+            Ok(summary) => summary,
+            Err(error) => {
+                error!("Run {} failed: {:#}", run, error);
+                RunSummary::failed(run, format!("{:#}", error))
+            }
+        };
+        summaries.push(summary);
+    }
+    Ok(summaries)
+}
+
+/// Align a run or a group of merged runs and report the jitter statistics.
+/// The first and last synchronized GET/FRIB pairs are reported by the alignment itself.
+fn dry_run_runs(config: &Config, runs: &[i32], write_map: bool) -> Result<RunSummary> {
+    let run = runs[0];
+    let mut reader = MergerReader::new(&config.merger_path, runs)?;
+    reader.read_ts(&config.sync)?;
+    reader.sync_ts(&config.sync)?;
+    let records = reader.sync_records();
+    JitterStatistics::from_records(&records, config.sync.jitter_warning).print();
+    if write_map {
+        let path = construct_sync_map_path(&config.sync_path, run);
+        write_sync_map(&records, &path)?;
+        info!("Wrote sync map {}", path.display());
+    }
+    Ok(reader_summary(run, &reader))
+}
+
+/// The summary of a run whose time stamps were synchronized by the reader.
+fn reader_summary(run: i32, reader: &MergerReader) -> RunSummary {
+    let matched = reader.get_sync.len();
+    RunSummary {
+        run,
+        status: RunStatus::Success,
+        get_events: reader.get_events.len(),
+        frib_events: reader.frib_events.len(),
+        get_only: reader.orphan_get,
+        frib_only: reader.orphan_frib,
+        empty: reader.empty_events,
+        matched,
+        dropped: reader.get_events.len() + reader.frib_events.len() - 2 * matched,
+        mismatches: reader.dropped_get.len() + reader.dropped_frib.len(),
+    }
+}
+
+/// Synchronize a run or a group of merged runs, turning an error into a failed summary.
+fn process_runs(config: &Config, runs: &[i32]) -> RunSummary {
+    let run = runs[0];
+    let log_path = construct_log_path(&config.sync_path, run);
+    if let Err(error) = start_run_log(&log_path) {
+        warn!("Could not open log file {}: {:#}", log_path.display(), error);
+    }
+    if runs.len() > 1 {
+        info!("Processing runs {:?} as run {}...", runs, run);
+    } else {
+        info!("Processing run {}...", &run);
+    }
+    let summary = match synchronize_run(config, runs) {
+        Ok(summary) => summary,
+        Err(error) => {
+            error!("Run {} failed: {:#}", run, error);
+            RunSummary::failed(run, format!("{:#}", error))
         }
-        // Process scalers
-        let parent_groups = reader.current_file.member_names()?;
-        if parent_groups.contains(&String::from("meta")) {
-            copy_scalers_010(&reader, &writer)?;
-        } else if parent_groups.contains(&String::from("events")) {
-            copy_scalers_020(&reader, &writer)?;
+    };
+    finish_run_log();
+    summary
+}
+
+/// Synchronize a single run, or a group of merged runs written to the output of the first run.
+/// If writing or closing the output fails, its temporary files and any parts already moved are removed.
+fn synchronize_run(config: &Config, runs: &[i32]) -> Result<RunSummary> {
+    let run = runs[0];
+    for merged in &runs[1..] {
+        if !construct_run_path(&config.merger_path, *merged).exists() {
+            return Err(eyre!("Merged run {} doesn't exist", merged));
+        }
+    }
+    let output_path = construct_run_path(&config.sync_path, run);
+    if output_path.exists() {
+        match config.output.existing {
+            ExistingPolicy::Error => {
+                return Err(eyre!(
+                    "Output {} already exists (see output.existing)",
+                    output_path.display()
+                ));
+            }
+            ExistingPolicy::Skip => {
+                if is_complete_output(&config.sync_path, run).unwrap_or(false) {
+                    info!("Output {} is complete, skipping...", output_path.display());
+                    return Ok(RunSummary::skipped(
+                        run,
+                        String::from("complete output already exists"),
+                    ));
+                }
+                info!("Output {} is incomplete, synchronizing again...", output_path.display());
+            }
+            ExistingPolicy::Overwrite => (),
+        }
+    }
+    let mut reader = MergerReader::new(&config.merger_path, runs)?;
+    // First read all data and create TS lists
+    info!("Reading time stamps...");
+    reader.read_ts(&config.sync)?;
+    // Synchronize TS
+    info!("Synchronizing time stamps...");
+    reader.sync_ts(&config.sync)?;
+    for path in remove_stale_temporaries(&config.sync_path, run)? {
+        warn!("Removed stale temporary file {}", path.display());
+    }
+    let mut writer = SyncWriter::new(&config.sync_path, run, &config.output)?;
+    match write_run(config, &mut reader, &mut writer) {
+        Ok(()) => writer.close()?,
+        Err(error) => {
+            writer.discard();
+            return Err(error);
         }
+    }
+    Ok(reader_summary(run, &reader))
+}
 
-        // Close file
-        writer.close()?;
+/// Write the synchronized events, the scalers and the sync metadata of a run using the sync lists.
+/// Scalers and sync metadata are written first so that they end up in the first part of the output.
+/// The sync map is written last, to a temporary file moved into place with the output.
+fn write_run(config: &Config, reader: &mut MergerReader, writer: &mut SyncWriter) -> Result<()> {
+    // Process scalers
+    copy_scalers(reader, writer)?;
+    writer.write_meta(config, reader)?;
+    // Write synchronized run using lists
+    info!("Writing synchronized file...");
+    let mut total = reader.get_sync.len();
+    if config.output.write_unmatched {
+        total += reader.get_events.len() + reader.frib_events.len() - 2 * reader.get_sync.len();
+    }
+    let mut progress = Progress::new(
+        &format!("Run {} writing", reader.runs()[0]),
+        "events",
+        total as u64,
+    );
+    for i in 0..reader.get_sync.len() {
+        let (get_index, frib_index) = (reader.get_sync[i], reader.frib_sync[i]);
+        reader.seek(reader.get_runs[get_index], reader.get_events[get_index])?;
+        let get_event = reader.read_event()?;
+        reader.seek(reader.frib_runs[frib_index], reader.frib_events[frib_index])?;
+        let frib_event = reader.read_event()?;
+        let (Some(get_event), Some(frib_event)) = (get_event, frib_event) else {
+            warn!(
+                "Could not read GET event {} or FRIB event {}, skipping...",
+                reader.get_label(get_index),
+                reader.frib_label(frib_index)
+            );
+            continue;
+        };
+        let bytes = get_event.get.as_ref().map_or(0, GetEvent::size_bytes)
+            + frib_event.frib.as_ref().map_or(0, FribEvent::size_bytes);
+        writer.write_combined(get_event, frib_event)?;
+        progress.inc(1, bytes);
     }
+    if config.output.write_unmatched {
+        info!("Writing unmatched events...");
+        for (run, event) in reader.unmatched_get() {
+            reader.seek(run, event)?;
+            if let Some(event) = reader.read_event()? {
+                writer.write_unmatched_get(&event)?;
+                progress.inc(1, event.get.as_ref().map_or(0, GetEvent::size_bytes));
+            }
+        }
+        for (run, event) in reader.unmatched_frib() {
+            reader.seek(run, event)?;
+            if let Some(event) = reader.read_event()? {
+                writer.write_unmatched_frib(&event)?;
+                progress.inc(1, event.frib.as_ref().map_or(0, FribEvent::size_bytes));
+            }
+        }
+    }
+    progress.finish();
+    info!("Writing sync map...");
+    let map_path = construct_sync_map_path(&config.sync_path, reader.runs()[0]);
+    write_sync_map(&reader.sync_records(), &writer.add_companion(&map_path))?;
     Ok(())
 }
 
@@ -139,15 +457,104 @@ fn main() -> Result<()> {
     let cli = Command::new("synchronizer")
         .arg_required_else_help(true)
         .subcommand(Command::new("new").about("Create a new template config file"))
+        .subcommand(
+            Command::new("dry-run")
+                .about("Align the runs and report the jitter without writing synchronized files")
+                .arg(
+                    Arg::new("sync-map")
+                        .long("sync-map")
+                        .action(ArgAction::SetTrue)
+                        .help("Write the sync map of each run to the sync path"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Describe a merger or synchronizer file")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .help("Path to the HDF5 file to inspect"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check a synchronized file against the merger files it was made from")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .help("Path to the synchronized file (the first part)"),
+                ),
+        )
         .arg(
             Arg::new("config")
                 .short('c')
                 .long("config")
                 .help("Path to a configuration file (YAML)"),
         )
+        .arg(
+            Arg::new("existing")
+                .long("existing")
+                .value_parser(["error", "skip", "overwrite"])
+                .help("What to do with runs whose output already exists (overrides output.existing)"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .global(true)
+                .help("Print more messages, repeat for even more (debug, trace)"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::Count)
+                .global(true)
+                .help("Print fewer messages, repeat for even fewer (warnings, errors)"),
+        )
+        .arg(
+            Arg::new("workers")
+                .short('w')
+                .long("workers")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Number of runs processed at the same time (overrides workers)"),
+        )
         .get_matches();
 
     println!("--------------------- AT-TPC Synchronizer ---------------------");
+    let verbose = cli.get_count("verbose");
+    let quiet = cli.get_count("quiet");
+    init_logging(LogLevel::default().adjusted(verbose, quiet));
+
+    // Handle the inspect subcommand, which doesn't need a configuration
+    if let Some(("inspect", inspect_args)) = cli.subcommand() {
+        let path = PathBuf::from(inspect_args.get_one::<String>("file").expect("We require a file"));
+        inspect(&path)?;
+        println!("-------------------------------------------------------------");
+        return Ok(());
+    }
+
+    // Handle the verify subcommand, which uses the configuration stored in the file
+    if let Some(("verify", verify_args)) = cli.subcommand() {
+        let path = PathBuf::from(verify_args.get_one::<String>("file").expect("We require a file"));
+        enable_progress_bars();
+        let discrepancies = verify(&path)?;
+        for discrepancy in &discrepancies {
+            println!("{}", discrepancy);
+        }
+        if discrepancies.is_empty() {
+            println!("No discrepancies found.");
+        } else {
+            println!("Found {} discrepancies!", discrepancies.len());
+        }
+        println!("-------------------------------------------------------------");
+        if !discrepancies.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let config_path = PathBuf::from(cli.get_one::<String>("config").expect("We require args"));
 
     // Handle the new subcommand
@@ -162,7 +569,14 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let config = Config::load(&config_path)?;
+    let mut config = Config::load(&config_path)?;
+    if let Some(existing) = cli.get_one::<String>("existing") {
+        config.output.existing = existing.parse()?;
+    }
+    if let Some(workers) = cli.get_one::<u64>("workers") {
+        config.workers = *workers as usize;
+    }
+    set_console_level(config.log_level.adjusted(verbose, quiet));
     println!(
         "Successfully loaded configuration from {}",
         config_path.display()
@@ -182,11 +596,22 @@ fn main() -> Result<()> {
         println!("-------------------------------------------------------------");
     }
 
-    println!("Synchronizing...");
-    synchronize(config)?;
+    enable_progress_bars();
+    let summaries = if let Some(("dry-run", dry_run_args)) = cli.subcommand() {
+        println!("Aligning without writing synchronized files...");
+        dry_run(&config, dry_run_args.get_flag("sync-map"))?
+    } else {
+        println!("Synchronizing...");
+        synchronize(config)?
+    };
     println!("Complete.");
+    print_summary(&summaries);
 
     println!("-------------------------------------------------------------");
 
+    if summaries.iter().any(|summary| summary.is_failed()) {
+        std::process::exit(1);
+    }
+
     Ok(())
 }
@@ -0,0 +1,115 @@
+//! Progress of the long passes over a run: a progress bar with the event and data rates
+//! when stdout is a terminal, or a line every few seconds otherwise.
+use crate::console::progress_bars;
+use human_bytes::human_bytes;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::info;
+use std::time::{Duration, Instant};
+
+/// Time between two updates of the rates shown on a progress bar
+const BAR_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+/// Time between two progress lines when stdout is not a terminal
+const LINE_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The progress of a pass over a known number of items (events or runs),
+/// with the amount of data processed.
+#[derive(Debug)]
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    label: String,
+    unit: &'static str,
+    total: u64,
+    position: u64,
+    bytes: u64,
+    start: Instant,
+    last_update: Instant,
+}
+
+impl Progress {
+    /// Start a pass over `total` items, labeled with the name of the pass.
+    pub fn new(label: &str, unit: &'static str, total: u64) -> Self {
+        let bar = progress_bars().map(|bars| {
+            let style = ProgressStyle::with_template(
+                "{prefix:>24} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}",
+            )
+            .expect("Invalid progress bar template")
+            .progress_chars("##-");
+            bars.add(
+                ProgressBar::new(total)
+                    .with_style(style)
+                    .with_prefix(label.to_string()),
+            )
+        });
+        let now = Instant::now();
+        Self {
+            bar,
+            label: label.to_string(),
+            unit,
+            total,
+            position: 0,
+            bytes: 0,
+            start: now,
+            last_update: now,
+        }
+    }
+
+    /// Advance the pass by a number of items, which amount to `bytes` of data.
+    pub fn inc(&mut self, items: u64, bytes: u64) {
+        self.position += items;
+        self.bytes += bytes;
+        if let Some(bar) = &self.bar {
+            bar.inc(items);
+        }
+        let interval = if self.bar.is_some() {
+            BAR_UPDATE_INTERVAL
+        } else {
+            LINE_UPDATE_INTERVAL
+        };
+        if self.last_update.elapsed() >= interval {
+            self.last_update = Instant::now();
+            self.update();
+        }
+    }
+
+    /// End the pass, reporting the total and the average rates.
+    pub fn finish(self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+        info!(
+            "{}: {} {} in {:.1} s ({})",
+            self.label,
+            self.position,
+            self.unit,
+            self.start.elapsed().as_secs_f64(),
+            self.rates()
+        );
+    }
+
+    /// Show the current rates on the bar, or print a progress line.
+    fn update(&self) {
+        match &self.bar {
+            Some(bar) => bar.set_message(self.rates()),
+            None => info!(
+                "{}: {}/{} {} ({:.0}%, {})",
+                self.label,
+                self.position,
+                self.total,
+                self.unit,
+                100.0 * self.position as f64 / self.total.max(1) as f64,
+                self.rates()
+            ),
+        }
+    }
+
+    /// The item and data rates since the start of the pass.
+    fn rates(&self) -> String {
+        let seconds = self.start.elapsed().as_secs_f64().max(f64::EPSILON);
+        format!(
+            "{:.0} {}/s, {}/s",
+            self.position as f64 / seconds,
+            self.unit,
+            human_bytes(self.bytes as f64 / seconds)
+        )
+    }
+}
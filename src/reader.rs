@@ -1,24 +1,82 @@
 //! Implementation of an attpc_merger Reader.
 //! Also contains code to check and synchronize time stamps
+use crate::alignment::align_global;
+use crate::clock::ClockModel;
+use crate::config::{AlignmentMode, ShortRunPolicy, SyncConfig};
+use crate::progress::Progress;
+use crate::sync_map::SyncRecord;
 use color_eyre::eyre::{eyre, Result};
+use log::{debug, info, warn};
 use hdf5_metno::File;
 use ndarray::{Array1, Array2};
 use std::path::{Path, PathBuf};
-// use hdf5_metno::types::VarLenUnicode;
+use hdf5_metno::types::VarLenUnicode;
 
 /// Enum for what version of the merger we are dealing with.
+/// Synchronizer outputs follow the 0.2.0 layout and are read as such.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum MergerVersion {
+pub enum MergerVersion {
     V010,
     V020,
+    Synchronizer,
     Invalid,
 }
 
+impl MergerVersion {
+    /// The merger version as a string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MergerVersion::V010 => "0.1.0",
+            MergerVersion::V020 => "0.2.0",
+            MergerVersion::Synchronizer => "synchronizer",
+            MergerVersion::Invalid => "invalid",
+        }
+    }
+}
+
+/// Detect the version of a merger file from its layout. Files with a sync_meta group
+/// or a synchronizer version on their events group are synchronizer outputs.
+pub fn detect_version(file: &File) -> Result<MergerVersion> {
+    let parent_groups = file.member_names()?;
+    if parent_groups.contains(&String::from("meta")) {
+        Ok(MergerVersion::V010)
+    } else if parent_groups.contains(&String::from("events")) {
+        let synchronized = parent_groups.contains(&String::from("sync_meta"))
+            || file
+                .group("events")?
+                .attr("version")
+                .and_then(|attr| attr.read_scalar::<VarLenUnicode>())
+                .is_ok_and(|version| version.as_str().starts_with("synchronizer"));
+        if synchronized {
+            Ok(MergerVersion::Synchronizer)
+        } else {
+            Ok(MergerVersion::V020)
+        }
+    } else {
+        Err(eyre!("Invalid Merger Version!"))
+    }
+}
+
+/// Parse the run number from a file named like run_XXXX.h5 or run_XXXX_partN.h5.
+fn run_number_from_path(path: &Path) -> Option<i32> {
+    let stem = path.file_stem()?.to_str()?.strip_prefix("run_")?;
+    stem.split('_').next()?.parse().ok()
+}
+
 /// Construct the formated run path from a parent path and run number.
 pub fn construct_run_path(path: &Path, run_number: i32) -> PathBuf {
     path.join(format!("run_{:0>4}.h5", run_number))
 }
 
+/// Total size in bytes of the merger files of a list of runs.
+pub fn get_total_merger_bytes(merger_path: &Path, runs: &[i32]) -> Result<u64> {
+    let mut total = 0;
+    for run in runs {
+        total += construct_run_path(merger_path, *run).metadata()?.len();
+    }
+    Ok(total)
+}
+
 /// Unified definition of a GET event from the merger
 #[derive(Debug)]
 pub struct GetEvent {
@@ -37,6 +95,20 @@ pub struct FribEvent {
     pub timestamp: u32,
 }
 
+impl GetEvent {
+    /// Size in bytes of the traces.
+    pub fn size_bytes(&self) -> u64 {
+        (self.traces.len() * std::mem::size_of::<i16>()) as u64
+    }
+}
+
+impl FribEvent {
+    /// Size in bytes of the traces and coincidence register.
+    pub fn size_bytes(&self) -> u64 {
+        ((self.traces.len() + self.coincidence.len()) * std::mem::size_of::<u16>()) as u64
+    }
+}
+
 /// Unified definition of a complete event from the merger
 #[derive(Debug)]
 pub struct MergerEvent {
@@ -46,96 +118,517 @@ pub struct MergerEvent {
     pub event: u64,
 }
 
+/// Time stamps of an event from the merger, read without the traces
+#[derive(Debug)]
+struct EventTimestamps {
+    get: Option<u64>,
+    frib: Option<u32>,
+}
+
+/// Summarize the matched events whose jitter is above the warning margin.
+/// Each of them is reported at the debug level.
+fn report_abnormal_jitter(count: usize, params: &SyncConfig) {
+    if count > 0 {
+        info!(
+            "Found abnormal TS jitter (above {}) in {} events",
+            params.jitter_warning, count
+        );
+    }
+}
+
+/// Runs and event numbers of the stream entries missing from the sorted sync list
+fn unmatched(runs: &[i32], events: &[u64], sync: &[usize]) -> Vec<(i32, u64)> {
+    let mut synced = sync.iter().peekable();
+    let mut result = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        if synced.peek() == Some(&&index) {
+            synced.next();
+        } else {
+            result.push((runs[index], *event));
+        }
+    }
+    result
+}
+
 /// Representation of a Reader for data from attpc_merger. It is
 /// capable of determining which version of the merger produced the
-/// data and then parsing it appropriately.
+/// data and then parsing it appropriately. Several consecutive runs
+/// can be read as a single stream of events.
 #[derive(Debug)]
 pub struct MergerReader {
-    merger_path: PathBuf,
     version: MergerVersion,
+    runs: Vec<i32>,
+    paths: Vec<PathBuf>,
     current_run: i32,
     pub current_file: File,
     pub current_event: u64,
     current_max_event: u64,
     ts_get_sync: Vec<u64>,
     ts_frib_sync: Vec<u64>,
+    pub get_events: Vec<u64>,
+    pub frib_events: Vec<u64>,
+    pub get_runs: Vec<i32>,
+    pub frib_runs: Vec<i32>,
+    pub orphan_get: usize,
+    pub orphan_frib: usize,
+    pub empty_events: usize,
     pub get_sync: Vec<usize>,
     pub frib_sync: Vec<usize>,
+    pub dropped_get: Vec<usize>,
+    pub dropped_frib: Vec<usize>,
+    pub clock: ClockModel,
+    pub frib_wraps: u64,
+    frib_modulus: u64,
 }
 
 impl MergerReader {
-    /// Create a new reader. The run is opened and initialized.
-    pub fn new(merger_path: &Path, run: i32) -> Result<Self> {
-        let file = File::open(construct_run_path(merger_path, run))?;
+    /// Create a new reader for a list of consecutive runs, read as one stream.
+    /// The first run is opened and initialized.
+    pub fn new(merger_path: &Path, runs: &[i32]) -> Result<Self> {
+        if runs.is_empty() {
+            return Err(eyre!("Attempting to create reader without runs!"));
+        }
+        let paths: Vec<PathBuf> = runs
+            .iter()
+            .map(|run| construct_run_path(merger_path, *run))
+            .collect();
+        let file = File::open(&paths[0])?;
+        Self::with_file(runs, paths, file)
+    }
+
+    /// Create a reader for a single file, which does not need to be in a merger path.
+    /// The run number is taken from the file name if it follows the run naming, otherwise it is 0.
+    pub fn open_file(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let run = run_number_from_path(path).unwrap_or(0);
+        Self::with_file(&[run], vec![path.to_path_buf()], file)
+    }
+
+    /// Create a reader with the first of its runs already opened.
+    /// The paths are the files of the runs, in the same order.
+    fn with_file(runs: &[i32], paths: Vec<PathBuf>, file: File) -> Result<Self> {
+        let run = runs[0];
         let mut reader = Self {
-            merger_path: merger_path.to_path_buf(),
             version: MergerVersion::Invalid,
+            runs: runs.to_vec(),
+            paths,
             current_run: run,
             current_file: file,
             current_event: 0,
             current_max_event: 0,
             ts_get_sync: Vec::<u64>::new(),
             ts_frib_sync: Vec::<u64>::new(),
+            get_events: Vec::<u64>::new(),
+            frib_events: Vec::<u64>::new(),
+            get_runs: Vec::<i32>::new(),
+            frib_runs: Vec::<i32>::new(),
+            orphan_get: 0,
+            orphan_frib: 0,
+            empty_events: 0,
             get_sync: Vec::<usize>::new(),
             frib_sync: Vec::<usize>::new(),
+            dropped_get: Vec::<usize>::new(),
+            dropped_frib: Vec::<usize>::new(),
+            clock: ClockModel::nominal(1.0),
+            frib_wraps: 0,
+            frib_modulus: 1 << 32,
         };
         reader.init_file()?;
         Ok(reader)
     }
 
-    // Read through all events and store all TS 
-    pub fn read_ts(&mut self)  -> Result<()> {
+    /// The runs read by this reader.
+    pub fn runs(&self) -> &[i32] {
+        &self.runs
+    }
+
+    /// Paths of the merger files read by this reader.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Total size in bytes of the files read by this reader.
+    pub fn input_bytes(&self) -> Result<u64> {
+        let mut total = 0;
+        for path in &self.paths {
+            total += path.metadata()?.len();
+        }
+        Ok(total)
+    }
+
+    /// Open one of the runs of this reader, if it is not the current one.
+    pub fn open_run(&mut self, run: i32) -> Result<()> {
+        if run != self.current_run {
+            let index = self
+                .runs
+                .iter()
+                .position(|r| *r == run)
+                .ok_or_else(|| eyre!("Run {} is not read by this reader", run))?;
+            self.current_file = File::open(&self.paths[index])?;
+            self.current_run = run;
+            self.init_file()?;
+        }
+        Ok(())
+    }
+
+    /// The format of the currently open file.
+    pub fn version(&self) -> &MergerVersion {
+        &self.version
+    }
+
+    /// The range of events of the currently open run (the maximum is exclusive).
+    /// The position is reset to the first event.
+    pub fn event_range(&mut self) -> Result<(u64, u64)> {
+        self.init_file()?;
+        Ok((self.current_event, self.current_max_event))
+    }
+
+    /// Total number of events in all runs of this reader.
+    pub fn total_events(&mut self) -> Result<u64> {
+        let mut total = 0;
+        for run in self.runs.clone() {
+            self.open_run(run)?;
+            self.init_file()?;
+            total += self.current_max_event.saturating_sub(self.current_event);
+        }
+        Ok(total)
+    }
+
+    /// Move to an event of one of the runs, so that it is read by the next read_event.
+    pub fn seek(&mut self, run: i32, event: u64) -> Result<()> {
+        self.open_run(run)?;
+        self.current_event = event;
+        Ok(())
+    }
+
+    /// The versions of the merger that produced the files of each run, in the order of the runs.
+    pub fn merger_versions(&self) -> Result<Vec<MergerVersion>> {
+        self.paths
+            .iter()
+            .map(|path| detect_version(&File::open(path)?))
+            .collect()
+    }
+
+    // Read through all events of all runs and store all TS in a GET and a FRIB stream.
+    // Events with only one side are orphans and only enter that side's stream.
+    // The FRIB TS counter is unwrapped every time it rolls over.
+    pub fn read_ts(&mut self, params: &SyncConfig) -> Result<()> {
+        let modulus = 1u64 << params.frib_timestamp_bits;
+        self.frib_modulus = modulus;
+        let mut previous: Option<u64> = None;
+        let total_events = self.total_events()?;
+        // Only the time stamps are read, so the data rate is the share of the input scanned
+        let bytes_per_event = self.input_bytes()? / total_events.max(1);
+        let mut progress = Progress::new(
+            &format!("Run {} time stamps", self.runs[0]),
+            "events",
+            total_events,
+        );
+        for run in self.runs.clone() {
+            if self.runs.len() > 1 {
+                info!("Reading time stamps of run {}...", run);
+            }
+            self.open_run(run)?;
+            // A run that is already open may have been read before
+            self.init_file()?;
+            self.read_run_ts(modulus, &mut previous, &mut progress, bytes_per_event)?;
+        }
+        progress.finish();
+        info!("Found {} FRIB time stamp wraparounds", self.frib_wraps);
+        info!(
+            "Found {} GET-only events, {} FRIB-only events and {} empty events",
+            self.orphan_get, self.orphan_frib, self.empty_events
+        );
+        Ok(())
+    }
+
+    // Read the TS of all events of the current run and append them to the streams.
+    fn read_run_ts(
+        &mut self,
+        modulus: u64,
+        previous: &mut Option<u64>,
+        progress: &mut Progress,
+        bytes_per_event: u64,
+    ) -> Result<()> {
         while self.current_event < self.current_max_event {
-            let get_event = self.read_event()?;
-            let frib_event = self.read_event()?;
-            self.ts_get_sync.push(get_event.unwrap().get.unwrap().timestamp_other);
-            self.ts_frib_sync.push(frib_event.unwrap().frib.unwrap().timestamp as u64);
+            let timestamps = self.read_timestamps()?;
+            match (&timestamps.get, &timestamps.frib) {
+                (Some(_), None) => self.orphan_get += 1,
+                (None, Some(_)) => self.orphan_frib += 1,
+                (None, None) => self.empty_events += 1,
+                (Some(_), Some(_)) => (),
+            }
+            if let Some(get_ts) = timestamps.get {
+                self.ts_get_sync.push(get_ts);
+                self.get_events.push(self.current_event);
+                self.get_runs.push(self.current_run);
+            }
+            if let Some(frib_ts) = timestamps.frib {
+                let frib_ts = frib_ts as u64 % modulus;
+                if let Some(previous) = *previous {
+                    if frib_ts < previous && previous - frib_ts > modulus / 2 {
+                        self.frib_wraps += 1;
+                    }
+                }
+                *previous = Some(frib_ts);
+                self.ts_frib_sync.push(frib_ts + self.frib_wraps * modulus);
+                self.frib_events.push(self.current_event);
+                self.frib_runs.push(self.current_run);
+            }
             self.current_event += 1;
+            progress.inc(1, bytes_per_event);
         }
         Ok(())
     }
 
+    /// Label of a GET stream entry: its event number, prefixed by its run when reading several runs.
+    pub fn get_label(&self, index: usize) -> String {
+        self.label(self.get_runs[index], self.get_events[index])
+    }
+
+    /// Label of a FRIB stream entry: its event number, prefixed by its run when reading several runs.
+    pub fn frib_label(&self, index: usize) -> String {
+        self.label(self.frib_runs[index], self.frib_events[index])
+    }
+
+    fn label(&self, run: i32, event: u64) -> String {
+        if self.runs.len() > 1 {
+            format!("{}:{}", run, event)
+        } else {
+            event.to_string()
+        }
+    }
+
     // Find synchronous TS between the GET and FRIB stream and make lists
-    pub fn sync_ts(&mut self) {
-        let mut tsd_get: Vec<i64> = Vec::new();
+    pub fn sync_ts(&mut self, params: &SyncConfig) -> Result<()> {
+        self.clock = ClockModel::nominal(params.clock_ratio);
+        let shortest = self.ts_get_sync.len().min(self.ts_frib_sync.len());
+        if shortest >= params.min_events {
+            self.align_run(params);
+        } else {
+            match params.short_run {
+                ShortRunPolicy::Error => {
+                    return Err(eyre!(
+                        "Run {} is too short to synchronize: {} GET events and {} FRIB events (sync.min_events is {})",
+                        self.runs[0],
+                        self.ts_get_sync.len(),
+                        self.ts_frib_sync.len(),
+                        params.min_events
+                    ));
+                }
+                ShortRunPolicy::Trivial => {
+                    warn!("Run {} is too short to synchronize, pairing events in order", self.runs[0]);
+                    self.get_sync = (0..shortest).collect();
+                    self.frib_sync = (0..shortest).collect();
+                }
+            }
+        }
+        if self.get_sync.is_empty() {
+            return Err(eyre!("No synchronized events found in run {}", self.runs[0]));
+        }
+        // get_sync and frib_sync should contain lists of matching data (as stream indices)
+        let last = self.get_sync.len() - 1;
+        info!(
+            "First synchronized pair is GET {}, FRIB {}",
+            self.get_label(self.get_sync[0]),
+            self.frib_label(self.frib_sync[0])
+        );
+        info!(
+            "Last synchronized pair is GET {}, FRIB {}",
+            self.get_label(self.get_sync[last]),
+            self.frib_label(self.frib_sync[last])
+        );
+        info!(
+            "A total of {} time stamp mismatches were found",
+            self.dropped_get.len() + self.dropped_frib.len()
+        );
+        let dropped_get: Vec<String> = self.dropped_get.iter().map(|i| self.get_label(*i)).collect();
+        let dropped_frib: Vec<String> = self.dropped_frib.iter().map(|i| self.frib_label(*i)).collect();
+        info!("Dropped {} GET events and {} FRIB events", dropped_get.len(), dropped_frib.len());
+        debug!("Dropped GET events: [{}]", dropped_get.join(", "));
+        debug!("Dropped FRIB events: [{}]", dropped_frib.join(", "));
+        Ok(())
+    }
+
+    /// Align the GET and FRIB streams of a run long enough to be synchronized,
+    /// fitting the clocks and aligning again if requested.
+    fn align_run(&mut self, params: &SyncConfig) {
         let mut tsd_frib: Vec<i64> = Vec::new();
         // Calculate TS differences
-        tsd_get.push(0);
-        for i in 1..self.ts_get_sync.len() {
-            tsd_get.push(self.ts_get_sync[i] as i64 - self.ts_get_sync[i-1] as i64);
-        }
         tsd_frib.push(0);
         for i in 1..self.ts_frib_sync.len() {
             tsd_frib.push(self.ts_frib_sync[i] as i64 - self.ts_frib_sync[i-1] as i64);
         }
+        // GET TS are converted to the FRIB clock before taking differences
+        self.align_streams(&tsd_frib, params);
+        if params.fit_clock {
+            if let Some(clock) = self.fit_clock(params) {
+                for segment in clock.segments.iter() {
+                    info!(
+                        "Fitted clock from GET TS {}: scale = {}, offset = {}",
+                        segment.get_start,
+                        segment.scale,
+                        segment.offset()
+                    );
+                }
+                info!("Synchronizing again with the fitted clock...");
+                self.clock = clock;
+                self.get_sync.clear();
+                self.frib_sync.clear();
+                self.dropped_get.clear();
+                self.dropped_frib.clear();
+                self.align_streams(&tsd_frib, params);
+            } else {
+                warn!("Not enough confidently matched events to fit the clocks, keeping the nominal clock ratio");
+            }
+        }
+    }
+
+    /// Describe the synchronization of every event in both streams, in stream order.
+    /// Matched pairs get one record each, unmatched events get a record of their own.
+    pub fn sync_records(&self) -> Vec<SyncRecord> {
+        let mut records = Vec::new();
+        let mut next_get = 0;
+        let mut next_frib = 0;
+        for (output_event, (&get, &frib)) in self.get_sync.iter().zip(self.frib_sync.iter()).enumerate() {
+            records.extend((next_get..get).map(|i| self.get_record(i)));
+            records.extend((next_frib..frib).map(|i| self.frib_record(i)));
+            let frib_record = self.frib_record(frib);
+            let mut record = SyncRecord {
+                output_event: Some(output_event as u64),
+                frib_run: frib_record.frib_run,
+                frib_event: frib_record.frib_event,
+                frib_timestamp: frib_record.frib_timestamp,
+                frib_timestamp_difference: frib_record.frib_timestamp_difference,
+                matched: true,
+                ..self.get_record(get)
+            };
+            if output_event > 0 {
+                let (get_prev, frib_prev) = (self.get_sync[output_event - 1], self.frib_sync[output_event - 1]);
+                let get_elapsed = self.clock.predict(self.ts_get_sync[get]) - self.clock.predict(self.ts_get_sync[get_prev]);
+                let frib_elapsed = self.ts_frib_sync[frib] as i64 - self.ts_frib_sync[frib_prev] as i64;
+                record.jitter = Some(get_elapsed.round() as i64 - frib_elapsed);
+            }
+            records.push(record);
+            next_get = get + 1;
+            next_frib = frib + 1;
+        }
+        records.extend((next_get..self.get_events.len()).map(|i| self.get_record(i)));
+        records.extend((next_frib..self.frib_events.len()).map(|i| self.frib_record(i)));
+        records
+    }
+
+    /// Runs and event numbers of the GET stream entries that were not synchronized.
+    pub fn unmatched_get(&self) -> Vec<(i32, u64)> {
+        unmatched(&self.get_runs, &self.get_events, &self.get_sync)
+    }
+
+    /// Runs and event numbers of the FRIB stream entries that were not synchronized.
+    pub fn unmatched_frib(&self) -> Vec<(i32, u64)> {
+        unmatched(&self.frib_runs, &self.frib_events, &self.frib_sync)
+    }
+
+    // Record of the GET side of a stream entry
+    fn get_record(&self, index: usize) -> SyncRecord {
+        SyncRecord {
+            get_run: Some(self.get_runs[index]),
+            get_event: Some(self.get_events[index]),
+            get_timestamp: Some(self.ts_get_sync[index]),
+            get_timestamp_difference: (index > 0)
+                .then(|| self.ts_get_sync[index] as i64 - self.ts_get_sync[index - 1] as i64),
+            ..Default::default()
+        }
+    }
+
+    // Record of the FRIB side of a stream entry, with the raw (wrapped) TS
+    fn frib_record(&self, index: usize) -> SyncRecord {
+        SyncRecord {
+            frib_run: Some(self.frib_runs[index]),
+            frib_event: Some(self.frib_events[index]),
+            frib_timestamp: Some(self.ts_frib_sync[index] % self.frib_modulus),
+            frib_timestamp_difference: (index > 0)
+                .then(|| self.ts_frib_sync[index] as i64 - self.ts_frib_sync[index - 1] as i64),
+            ..Default::default()
+        }
+    }
+
+    // Align the GET stream, predicted with the current clock model, to the FRIB stream
+    fn align_streams(&mut self, tsd_frib: &[i64], params: &SyncConfig) {
+        let tsd_get = self.predicted_get_differences();
+        match params.mode {
+            AlignmentMode::Greedy => self.sync_greedy(&tsd_get, tsd_frib, params),
+            AlignmentMode::Global => self.sync_global(&tsd_get, tsd_frib, params),
+        }
+    }
+
+    // Differences of the GET TS predicted in the FRIB clock by the current clock model
+    fn predicted_get_differences(&self) -> Vec<i64> {
+        let predicted: Vec<f64> = self
+            .ts_get_sync
+            .iter()
+            .map(|ts| self.clock.predict(*ts))
+            .collect();
+        let mut tsd_get: Vec<i64> = vec![0];
+        for i in 1..predicted.len() {
+            tsd_get.push((predicted[i] - predicted[i-1]).round() as i64);
+        }
+        tsd_get
+    }
+
+    // Fit the clock model to the matched events whose jitter is within the warning margin.
+    // Only the first fit_events of them are used, unless the fit is piecewise.
+    fn fit_clock(&self, params: &SyncConfig) -> Option<ClockModel> {
+        let mut pairs: Vec<(u64, u64)> = Vec::new();
+        for i in 1..self.get_sync.len() {
+            let (get, frib) = (self.get_sync[i], self.frib_sync[i]);
+            let (get_prev, frib_prev) = (self.get_sync[i-1], self.frib_sync[i-1]);
+            let jitter = (self.clock.predict(self.ts_get_sync[get]) - self.clock.predict(self.ts_get_sync[get_prev]))
+                - (self.ts_frib_sync[frib] as f64 - self.ts_frib_sync[frib_prev] as f64);
+            if jitter.abs() <= params.jitter_warning as f64 {
+                pairs.push((self.ts_get_sync[get], self.ts_frib_sync[frib]));
+            }
+            if params.drift_window == 0 && pairs.len() >= params.fit_events {
+                break;
+            }
+        }
+        ClockModel::fit(&pairs, params.drift_window)
+    }
+
+    // Walk both streams from the first aligned events, skipping the extra event
+    // of whichever stream lost lock.
+    fn sync_greedy(&mut self, tsd_get: &[i64], tsd_frib: &[i64], params: &SyncConfig) {
         // Check whether first events are aligned
         let mut offset: usize = 0;
         let mut get_first: usize = 0;
         let mut frib_first: usize = 0;
-        if (tsd_get[1] - tsd_frib[1]).abs() > 100 { // not aligned!
-            println!("First events are not aligned!");
+        if (tsd_get[1] - tsd_frib[1]).abs() > params.alignment_threshold { // not aligned!
+            info!("First events are not aligned!");
             // Align time stamps after finding matching pattern of TS
             let mut get_pattern: i64;
             let mut frib_pattern: i64;
-            let depth: usize = 5;
             for start in 0..tsd_get.len()/2 { // Good starting event should be before half of data!
                 while offset < tsd_get.len()/2 && offset < tsd_frib.len()/2 {
+                    if start + offset + params.pattern_depth > tsd_get.len()
+                        || start + offset + params.pattern_depth > tsd_frib.len()
+                    { // pattern would run past the end of a stream
+                        break;
+                    }
                     get_pattern = 0;
                     frib_pattern = 0;
-                    for j in 1..depth { // look for matching pattern over depth TS differences
+                    for j in 1..params.pattern_depth { // look for matching pattern over depth TS differences
                         get_pattern += (tsd_get[start +j + offset] - tsd_frib[start + j]).abs();
                         frib_pattern += (tsd_frib[start + j + offset] - tsd_get[start + j]).abs();
                     }
-                    if get_pattern < 5 { // GET ahead of FRIB
+                    if get_pattern < params.pattern_tolerance { // GET ahead of FRIB
                         get_first = start + offset;
                         frib_first = start;
-                        println!("Fist aligned event is GET {}, FRIB {}", get_first, frib_first);
+                        info!("First aligned event is GET {}, FRIB {}", self.get_label(get_first), self.frib_label(frib_first));
                         break;
                     }
-                    if frib_pattern < 5 { // FRIB ahead of GET
+                    if frib_pattern < params.pattern_tolerance { // FRIB ahead of GET
                         frib_first = start + offset;
                         get_first = start;
-                        println!("Fist aligned event is GET {}, FRIB {}", get_first, frib_first);
+                        info!("First aligned event is GET {}, FRIB {}", self.get_label(get_first), self.frib_label(frib_first));
                         break;
                     }
                     offset += 1;
@@ -147,43 +640,65 @@ impl MergerReader {
             }
         }
         // Now compare differences within jitter margin and make list of matching events
-        // let mut get_sync: Vec<usize> = Vec::new();
-        // let mut frib_sync: Vec<usize> = Vec::new();
-       // spdlog::info!("GET TS: {}, FRIB TS: {}", tsd_get.len(), tsd_frib.len());
-        offset = 0;
         // set first synchronized event from alignment
         self.get_sync.push(get_first);
         self.frib_sync.push(frib_first);
+        let mut i = get_first + 1;
+        let mut j = frib_first + 1;
         let mut jitter: i64;
-        for i in 1..tsd_get.len() - get_first {
-            if i < tsd_frib.len() - offset - frib_first {
-                jitter = tsd_get[i+get_first] - tsd_frib[i+frib_first+offset];
-                if jitter > 1000 { // FRIB stream got its next event earlier than GET
-                    // spdlog::info!("Found time stamp mismatch at event {}: get={} vs frib={}", i, tsd_get[i], tsd_frib[i]);
-                    offset += 1;
-                } else if jitter > 5 {
-                    println!("Found abnormal TS jitter of {} in event {}", jitter, i + get_first);
-                }
-                self.get_sync.push(i + get_first);
-                self.frib_sync.push(i + offset + frib_first);
-                // spdlog::info!("Get event {} matches FRIB event {}", get_evt[i], frib_evt[i]);
-            } else { // no more FRIB events to sync
+        let mut abnormal_jitter = 0;
+        while i < tsd_get.len() && j < tsd_frib.len() {
+            jitter = tsd_get[i] - tsd_frib[j];
+            if jitter > params.mismatch_threshold { // FRIB stream got its next event earlier than GET
+                self.dropped_frib.push(j);
+                j += 1;
+            } else if jitter < -params.mismatch_threshold { // GET stream got its next event earlier than FRIB
+                self.dropped_get.push(i);
+                i += 1;
+            } else if jitter.abs() > params.jitter_warning {
+                debug!("Found abnormal TS jitter of {} in event {}", jitter, self.get_label(i));
+                abnormal_jitter += 1;
+            }
+            if i >= tsd_get.len() || j >= tsd_frib.len() { // no more events to sync
                 break;
             }
+            self.get_sync.push(i);
+            self.frib_sync.push(j);
+            i += 1;
+            j += 1;
         }
-        // get_sync and frib_sync should contain lists of matching data
-        println!("First GET event synchronized is {}", self.get_sync[0]);
-        println!("Last GET event synchronized is {}", self.get_sync[self.get_sync.len()-1]);
-        println!("A total of {} time stamp mismatches were found", offset);
+        report_abnormal_jitter(abnormal_jitter, params);
     }
 
-    /// Read the next event from the run set.
-    /// If the currently open run is finished, the next run that
-    /// exists within the range is opened. If there is no more data
-    /// to be read it returns a None.
+    // Align both streams with a global sequence alignment. Events skipped
+    // between two matched pairs are recorded as dropped.
+    fn sync_global(&mut self, tsd_get: &[i64], tsd_frib: &[i64], params: &SyncConfig) {
+        let (get_sync, frib_sync) = align_global(tsd_get, tsd_frib, params);
+        let (Some(get_first), Some(frib_first)) = (get_sync.first(), frib_sync.first()) else {
+            return;
+        };
+        info!("First aligned event is GET {}, FRIB {}", self.get_label(*get_first), self.frib_label(*frib_first));
+        let mut abnormal_jitter = 0;
+        for i in 1..get_sync.len() {
+            self.dropped_get.extend(get_sync[i - 1] + 1..get_sync[i]);
+            self.dropped_frib.extend(frib_sync[i - 1] + 1..frib_sync[i]);
+            let jitter = tsd_get[get_sync[i - 1] + 1..=get_sync[i]].iter().sum::<i64>()
+                - tsd_frib[frib_sync[i - 1] + 1..=frib_sync[i]].iter().sum::<i64>();
+            if jitter.abs() > params.jitter_warning {
+                debug!("Found abnormal TS jitter of {} in event {}", jitter, self.get_label(get_sync[i]));
+                abnormal_jitter += 1;
+            }
+        }
+        report_abnormal_jitter(abnormal_jitter, params);
+        self.get_sync = get_sync;
+        self.frib_sync = frib_sync;
+    }
+
+    /// Read the current event from the currently open run.
+    /// Use seek to move to an event of another run.
     pub fn read_event(&mut self) -> Result<Option<MergerEvent>> {
         let result = match self.version {
-            MergerVersion::V020 => self.read_event_020(),
+            MergerVersion::V020 | MergerVersion::Synchronizer => self.read_event_020(),
             MergerVersion::V010 => self.read_event_010(),
             MergerVersion::Invalid => Err(eyre!("Attempting to read event from invalid reader!")),
         };
@@ -192,48 +707,74 @@ impl MergerReader {
         result
     }
 
+    /// Read the time stamps of the current event, without reading any trace data.
+    fn read_timestamps(&self) -> Result<EventTimestamps> {
+        match self.version {
+            MergerVersion::V020 | MergerVersion::Synchronizer => self.read_timestamps_020(),
+            MergerVersion::V010 => self.read_timestamps_010(),
+            MergerVersion::Invalid => Err(eyre!("Attempting to read time stamps from invalid reader!")),
+        }
+    }
+
+    /// Read the time stamps of an event from the modern merger format.
+    /// Only the attributes are read; the datasets are opened but never loaded.
+    fn read_timestamps_020(&self) -> Result<EventTimestamps> {
+        let event_group = self
+            .current_file
+            .group("events")?
+            .group(&format!("event_{}", self.current_event))?;
+
+        let mut get = None;
+        let mut frib = None;
+        if let Ok(get_data) = event_group.dataset("get_traces") {
+            get = Some(get_data.attr("timestamp_other")?.read_scalar()?);
+        }
+        if let Ok(frib_group) = event_group.group("frib_physics") {
+            frib = Some(frib_group.attr("timestamp")?.read_scalar()?);
+        }
+        Ok(EventTimestamps { get, frib })
+    }
+
+    /// Read the time stamps of an event from the 0.1.0 merger format.
+    /// Only the small header datasets are read.
+    fn read_timestamps_010(&self) -> Result<EventTimestamps> {
+        let mut get = None;
+        let mut frib = None;
+        let get_group = self.current_file.group("get")?;
+        if let Ok(get_header) = get_group.dataset(&format!("evt{}_header", self.current_event)) {
+            get = Some(get_header.read_1d::<f64>()?[2] as u64);
+        }
+        let frib_evt_group = self.current_file.group("frib")?.group("evt")?;
+        if let Ok(frib_header) =
+            frib_evt_group.dataset(&format!("evt{}_header", self.current_event))
+        {
+            frib = Some(frib_header.read_1d::<u32>()?[1]);
+        }
+        Ok(EventTimestamps { get, frib })
+    }
+
     /// Initialize the current file, and update our state
     fn init_file(&mut self) -> Result<()> {
-        let parent_groups = self.current_file.member_names()?;
-        if parent_groups.contains(&String::from("meta")) {
-            self.version = MergerVersion::V010;
-            let meta_group = self.current_file.group("meta")?;
-            let meta_data = meta_group.dataset("meta")?;
-            let meta_array = meta_data.read_1d::<u64>()?;
-            self.current_event = meta_array[0];
-            self.current_max_event = meta_array[2];
-        } else if parent_groups.contains(&String::from("events")) {
-            self.version = MergerVersion::V020;
-            let event_group = self.current_file.group("events")?;
-            self.current_event = event_group.attr("min_event")?.read_scalar::<u64>()?;
-            self.current_max_event = event_group.attr("max_event")?.read_scalar::<u64>()?;
-        } else {
-            return Err(eyre!("Invalid Merger Version!"));
+        self.version = detect_version(&self.current_file)?;
+        match self.version {
+            MergerVersion::V010 => {
+                let meta_group = self.current_file.group("meta")?;
+                let meta_data = meta_group.dataset("meta")?;
+                let meta_array = meta_data.read_1d::<u64>()?;
+                self.current_event = meta_array[0];
+                self.current_max_event = meta_array[2];
+            }
+            MergerVersion::V020 | MergerVersion::Synchronizer => {
+                let event_group = self.current_file.group("events")?;
+                self.current_event = event_group.attr("min_event")?.read_scalar::<u64>()?;
+                self.current_max_event = event_group.attr("max_event")?.read_scalar::<u64>()?;
+            }
+            MergerVersion::Invalid => return Err(eyre!("Invalid Merger Version!")),
         }
 
         Ok(())
     }
 
-    /// Find the next available file in the run range.
-    /// If there are no more runs, returns None.
-    // fn find_next_file(&mut self) -> Result<Option<()>> {
-    //     let mut path;
-    //     loop {
-    //         self.current_run += 1;
-    //         if self.current_run > self.max_run {
-    //             return Ok(None);
-    //         }
-    //         path = construct_run_path(&self.merger_path, self.current_run);
-    //         if !path.exists() {
-    //             continue;
-    //         }
-    //         break;
-    //     }
-    //     self.current_file = File::open(path)?;
-    //     self.init_file()?;
-    //     Ok(Some(()))
-    // }
-
     /// Read an event from the modern merger format.
     fn read_event_020(&mut self) -> Result<Option<MergerEvent>> {
         let event_group = self
@@ -4,6 +4,7 @@
 // use super::reader::construct_run_path;
 use color_eyre::eyre::{eyre, Result};
 // use hdf5_metno::File;
+use hdf5_metno::Group;
 // use polars::prelude::*;
 use crate::MergerReader;
 use crate::SyncWriter;
@@ -11,10 +12,41 @@ use crate::SyncWriter;
 // use std::str::FromStr;
 
 
+/// Copy the scalers of all runs of the reader to the synchronized HDF5 file.
+/// The scalers of merged runs continue the numbering of the previous run.
+pub fn copy_scalers(reader: &mut MergerReader, writer: &SyncWriter) -> Result<()> {
+    let scaler_groupw = writer.current_file.create_group("scalers")?;
+    let mut range: Option<(u32, u32)> = None;
+    let mut next: Option<u32> = None;
+    for run in reader.runs().to_vec() {
+        reader.open_run(run)?;
+        let parent_groups = reader.current_file.member_names()?;
+        let (min_event, max_event, next_event) = if parent_groups.contains(&String::from("meta")) {
+            copy_scalers_010(reader, &scaler_groupw, next)?
+        } else if parent_groups.contains(&String::from("events")) {
+            copy_scalers_020(reader, &scaler_groupw, next)?
+        } else {
+            return Err(eyre!("Invalid Merger Version!"));
+        };
+        range = Some(match range {
+            Some((min, _)) => (min, max_event),
+            None => (min_event, max_event),
+        });
+        next = Some(next_event);
+    }
+    if let Some((min_event, max_event)) = range {
+        scaler_groupw.new_attr::<u32>().create("min_event")?.write_scalar(&min_event)?;
+        scaler_groupw.new_attr::<u32>().create("max_event")?.write_scalar(&max_event)?;
+    }
+    Ok(())
+}
+
 // Function to copy scalers from original HDF5 (version 010) to synchronized HDF5 (version 020)
-pub fn copy_scalers_010(reader: &MergerReader, writer: &SyncWriter) -> Result<()> {
+// Scalers are numbered from start (0 by default). Returns the min_event and max_event
+// attributes of the copied scalers and the next free scaler number.
+fn copy_scalers_010(reader: &MergerReader, scaler_groupw: &Group, start: Option<u32>) -> Result<(u32, u32, u32)> {
     let scaler_group = reader.current_file.group("frib")?.group("scaler")?;
-    let scaler_groupw = writer.current_file.create_group("scalers")?;
+    let start = start.unwrap_or(0);
     let mut scaler: u32 = 0;
     loop {
         if let Ok(event) = scaler_group.dataset(&format!("scaler{scaler}_data")) {
@@ -22,7 +54,7 @@ pub fn copy_scalers_010(reader: &MergerReader, writer: &SyncWriter) -> Result<()
             let scaler_eventw = scaler_groupw
                 .new_dataset_builder()
                 .with_data(&data)
-                .create(format!("event_{}", scaler).as_str())?;
+                .create(format!("event_{}", start + scaler).as_str())?;
             let header = scaler_group.dataset(&format!("scaler{scaler}_header"));
             let attr = header?.read_1d::<u32>()?;
             scaler_eventw.new_attr::<u32>().create("start_offset")?.write_scalar(&attr[0])?;
@@ -34,24 +66,24 @@ pub fn copy_scalers_010(reader: &MergerReader, writer: &SyncWriter) -> Result<()
         }
         scaler += 1;
     }
-    scaler_groupw.new_attr::<u32>().create("min_event")?.write_scalar(&0)?;
-    scaler_groupw.new_attr::<u32>().create("max_event")?.write_scalar(&scaler)?;
-    Ok(())
+    Ok((start, start + scaler, start + scaler))
 }
 
 // Function to copy scalers from original HDF5 (version 020) to synchronized HDF5 (version 020)
-pub fn copy_scalers_020(reader: &MergerReader, writer: &SyncWriter) -> Result<()> {
-    let scaler_groupw = writer.current_file.create_group("scalers")?;
+// Scalers keep their original numbers unless a start is given. Returns the min_event and
+// max_event attributes of the copied scalers and the next free scaler number.
+fn copy_scalers_020(reader: &MergerReader, scaler_groupw: &Group, start: Option<u32>) -> Result<(u32, u32, u32)> {
     let scaler_group = reader.current_file.group("scalers")?;
     let scaler_min = scaler_group.attr("min_event")?.read_scalar::<u32>()?;
     let scaler_max = scaler_group.attr("max_event")?.read_scalar::<u32>()?;
+    let start = start.unwrap_or(scaler_min);
     for scaler in scaler_min..(scaler_max + 1) {
         if let Ok(event) = scaler_group.dataset(&format!("event{scaler}_data")) {
             let data = event.read_1d::<u32>()?;
             let scaler_eventw = scaler_groupw
                 .new_dataset_builder()
                 .with_data(&data)
-                .create(format!("event_{}", scaler).as_str())?;
+                .create(format!("event_{}", start + scaler - scaler_min).as_str())?;
             let start_offset = event.attr("start_offset")?.read_scalar::<u32>()?;
             let stop_offset = event.attr("stop_offset")?.read_scalar::<u32>()?;
             let timestamp = event.attr("timestamp")?.read_scalar::<u32>()?;
@@ -62,9 +94,6 @@ pub fn copy_scalers_020(reader: &MergerReader, writer: &SyncWriter) -> Result<()
             scaler_eventw.new_attr::<u32>().create("incremental")?.write_scalar(&incremental)?;
         }
     }
-    let min_event = scaler_group.attr("min_event")?.read_scalar::<u32>()?;
-    let max_event = scaler_group.attr("max_event")?.read_scalar::<u32>()?;
-    scaler_groupw.new_attr::<u32>().create("min_event")?.write_scalar(&min_event)?;
-    scaler_groupw.new_attr::<u32>().create("max_event")?.write_scalar(&max_event)?;
-    Ok(())
-}
\ No newline at end of file
+    let max_event = start + scaler_max - scaler_min;
+    Ok((start, max_event, max_event + 1))
+}
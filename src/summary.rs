@@ -0,0 +1,156 @@
+//! Summary of the synchronization of each run in a batch.
+use crate::sync_map::SyncRecord;
+use log::info;
+
+/// The outcome of processing a single run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunStatus {
+    Success,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Statistics of the synchronization of a single run.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub run: i32,
+    pub status: RunStatus,
+    pub get_events: usize,
+    pub frib_events: usize,
+    pub get_only: usize,
+    pub frib_only: usize,
+    pub empty: usize,
+    pub matched: usize,
+    pub dropped: usize,
+    pub mismatches: usize,
+}
+
+impl RunSummary {
+    /// A summary for a run that could not be processed.
+    pub fn failed(run: i32, cause: String) -> Self {
+        Self {
+            run,
+            status: RunStatus::Failed(cause),
+            get_events: 0,
+            frib_events: 0,
+            get_only: 0,
+            frib_only: 0,
+            empty: 0,
+            matched: 0,
+            dropped: 0,
+            mismatches: 0,
+        }
+    }
+
+    /// A summary for a run that was not processed.
+    pub fn skipped(run: i32, reason: String) -> Self {
+        Self {
+            status: RunStatus::Skipped(reason),
+            ..Self::failed(run, String::new())
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status, RunStatus::Failed(_))
+    }
+}
+
+/// Statistics of the residual jitter of the matched events of a run, in FRIB ticks.
+#[derive(Debug, Clone, Default)]
+pub struct JitterStatistics {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub max_abs: i64,
+    pub warning: i64,
+    pub above_warning: usize,
+}
+
+impl JitterStatistics {
+    /// Compute the statistics of the jitter of the matched records of a sync map,
+    /// counting the events whose jitter is above the warning margin.
+    pub fn from_records(records: &[SyncRecord], warning: i64) -> Self {
+        let jitters: Vec<i64> = records
+            .iter()
+            .filter(|record| record.matched)
+            .filter_map(|record| record.jitter)
+            .collect();
+        if jitters.is_empty() {
+            return Self {
+                warning,
+                ..Self::default()
+            };
+        }
+        let count = jitters.len();
+        let mean = jitters.iter().sum::<i64>() as f64 / count as f64;
+        let variance = jitters
+            .iter()
+            .map(|jitter| (*jitter as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        Self {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+            max_abs: jitters.iter().map(|jitter| jitter.abs()).max().unwrap_or(0),
+            warning,
+            above_warning: jitters.iter().filter(|jitter| jitter.abs() > warning).count(),
+        }
+    }
+
+    pub fn print(&self) {
+        info!(
+            "Jitter of {} matched events: mean {:.2}, std. dev. {:.2}, max |jitter| {}, {} above {}",
+            self.count,
+            self.mean,
+            self.std_dev,
+            self.max_abs,
+            self.above_warning,
+            self.warning
+        );
+    }
+}
+
+/// Print a table of the run summaries, followed by the reason for each skipped or failed run.
+pub fn print_summary(summaries: &[RunSummary]) {
+    println!(
+        "{:>6} {:>8} {:>10} {:>11} {:>8} {:>9} {:>6} {:>10} {:>10} {:>11}",
+        "Run",
+        "Status",
+        "GET events",
+        "FRIB events",
+        "GET-only",
+        "FRIB-only",
+        "Empty",
+        "Matched",
+        "Dropped",
+        "Mismatches"
+    );
+    for summary in summaries {
+        let status = match summary.status {
+            RunStatus::Success => "ok",
+            RunStatus::Skipped(_) => "skipped",
+            RunStatus::Failed(_) => "failed",
+        };
+        println!(
+            "{:>6} {:>8} {:>10} {:>11} {:>8} {:>9} {:>6} {:>10} {:>10} {:>11}",
+            summary.run,
+            status,
+            summary.get_events,
+            summary.frib_events,
+            summary.get_only,
+            summary.frib_only,
+            summary.empty,
+            summary.matched,
+            summary.dropped,
+            summary.mismatches
+        );
+    }
+    for summary in summaries {
+        match &summary.status {
+            RunStatus::Skipped(reason) => println!("Run {} skipped: {}", summary.run, reason),
+            RunStatus::Failed(cause) => println!("Run {} failed: {}", summary.run, cause),
+            RunStatus::Success => (),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! The sync map: a Parquet table describing how every event of a run was synchronized.
+use color_eyre::eyre::Result;
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Construct the formated sync map path from a parent path and run number.
+pub fn construct_sync_map_path(path: &Path, run_number: i32) -> PathBuf {
+    path.join(format!("run_{:0>4}_sync.parquet", run_number))
+}
+
+/// One row of the sync map. Matched events have both a GET and a FRIB side,
+/// dropped events only have the side of the stream they belong to and no output event.
+#[derive(Debug, Clone, Default)]
+pub struct SyncRecord {
+    pub output_event: Option<u64>,
+    pub get_run: Option<i32>,
+    pub get_event: Option<u64>,
+    pub frib_run: Option<i32>,
+    pub frib_event: Option<u64>,
+    pub get_timestamp: Option<u64>,
+    pub frib_timestamp: Option<u64>,
+    pub get_timestamp_difference: Option<i64>,
+    pub frib_timestamp_difference: Option<i64>,
+    pub jitter: Option<i64>,
+    pub matched: bool,
+}
+
+/// Write the sync map of a run to a Parquet file.
+pub fn write_sync_map(records: &[SyncRecord], path: &Path) -> Result<()> {
+    let mut df = DataFrame::new(vec![
+        Series::new(
+            "output_event".into(),
+            records.iter().map(|r| r.output_event).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "get_run".into(),
+            records.iter().map(|r| r.get_run).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "get_event".into(),
+            records.iter().map(|r| r.get_event).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "frib_run".into(),
+            records.iter().map(|r| r.frib_run).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "frib_event".into(),
+            records.iter().map(|r| r.frib_event).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "get_timestamp".into(),
+            records.iter().map(|r| r.get_timestamp).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "frib_timestamp".into(),
+            records.iter().map(|r| r.frib_timestamp).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "get_timestamp_difference".into(),
+            records
+                .iter()
+                .map(|r| r.get_timestamp_difference)
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "frib_timestamp_difference".into(),
+            records
+                .iter()
+                .map(|r| r.frib_timestamp_difference)
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "jitter".into(),
+            records.iter().map(|r| r.jitter).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "matched".into(),
+            records.iter().map(|r| r.matched).collect::<Vec<_>>(),
+        ),
+    ])?;
+    let file = std::fs::File::create(path)?;
+    ParquetWriter::new(file).finish(&mut df)?;
+    Ok(())
+}
@@ -0,0 +1,330 @@
+//! Verification of a synchronized output against the merger files it was made from.
+use crate::clock::{ClockModel, ClockSegment};
+use crate::config::Config;
+use crate::progress::Progress;
+use crate::reader::{construct_run_path, MergerReader, MergerVersion};
+use crate::writer::construct_part_path;
+use color_eyre::eyre::{eyre, Result};
+use hdf5_metno::types::VarLenUnicode;
+use hdf5_metno::{File, Group};
+use std::path::{Path, PathBuf};
+
+/// The source of a synchronized event, from its orig_* attributes.
+struct EventSource {
+    get_run: i32,
+    get_event: u64,
+    frib_run: i32,
+    frib_event: u64,
+}
+
+/// The time stamps of the previous verified event, used to check the jitter.
+struct PreviousTimestamps {
+    event: u64,
+    get: u64,
+    frib: u32,
+}
+
+/// The state of the verification of the events of an output.
+struct EventVerifier {
+    source: MergerReader,
+    clock: ClockModel,
+    jitter_warning: i64,
+    modulus: u64,
+    previous: Option<PreviousTimestamps>,
+    discrepancies: Vec<String>,
+}
+
+/// Verify a synchronized output (all of its parts) against its merger input.
+/// Every event is re-read and compared with the source GET and FRIB events, the jitter
+/// between consecutive events is checked against the configured jitter warning threshold,
+/// and the event ranges and scaler counts are checked.
+/// Returns the list of discrepancies found, empty if the output is consistent.
+pub fn verify(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path)?;
+    let meta = file.group("sync_meta").map_err(|_| {
+        eyre!(
+            "{} has no sync_meta group; verify the first part of a synchronizer output",
+            path.display()
+        )
+    })?;
+    let config_yaml = meta.attr("config")?.read_scalar::<VarLenUnicode>()?;
+    let config: Config = serde_yaml::from_str(config_yaml.as_str())?;
+    let clock = read_clock(&meta)?;
+    let run = file
+        .group("events")?
+        .group("event_0")
+        .ok()
+        .and_then(|event| event.attr("orig_run").ok())
+        .and_then(|attr| attr.read_scalar::<i32>().ok());
+    let runs = match meta.dataset("input_runs") {
+        Ok(dataset) => dataset.read_raw::<i32>()?,
+        Err(_) => {
+            vec![run.ok_or_else(|| eyre!("Could not find the input run of {}", path.display()))?]
+        }
+    };
+    println!(
+        "Verifying {} against runs {:?} in {}",
+        path.display(),
+        runs,
+        config.merger_path.display()
+    );
+    println!(
+        "Jitter threshold: {} ticks (sync.jitter_warning)",
+        config.sync.jitter_warning
+    );
+
+    let mut verifier = EventVerifier {
+        source: MergerReader::new(&config.merger_path, &runs)?,
+        clock,
+        jitter_warning: config.sync.jitter_warning,
+        modulus: 1u64 << config.sync.frib_timestamp_bits,
+        previous: None,
+        discrepancies: Vec::new(),
+    };
+    let mut expected_min = None;
+    for part_path in output_parts(path, runs[0]) {
+        let mut output = MergerReader::open_file(&part_path)?;
+        let (min_event, max_event) = output.event_range()?;
+        if *output.version() != MergerVersion::Synchronizer {
+            verifier.discrepancies.push(format!(
+                "{} is not a synchronizer output",
+                part_path.display()
+            ));
+            continue;
+        }
+        if let Some(expected) = expected_min {
+            if min_event != expected {
+                verifier.discrepancies.push(format!(
+                    "{}: min_event is {}, expected {} after the previous part",
+                    part_path.display(),
+                    min_event,
+                    expected
+                ));
+            }
+        }
+        expected_min = Some(max_event);
+        let events_group = output.current_file.group("events")?;
+        let n_events = events_group
+            .member_names()?
+            .iter()
+            .filter(|name| name.starts_with("event_"))
+            .count() as u64;
+        if max_event.saturating_sub(min_event) != n_events {
+            verifier.discrepancies.push(format!(
+                "{}: max_event {} and min_event {} don't match the {} events in the file",
+                part_path.display(),
+                max_event,
+                min_event,
+                n_events
+            ));
+        }
+
+        let mut progress = Progress::new(
+            &format!("Verifying {}", part_path.display()),
+            "events",
+            max_event.saturating_sub(min_event),
+        );
+        for event in min_event..max_event {
+            if let Err(error) = verifier.verify_event(&mut output, &events_group, event) {
+                verifier
+                    .discrepancies
+                    .push(format!("Event {}: {:#}", event, error));
+            }
+            progress.inc(1, 0);
+        }
+        progress.finish();
+    }
+
+    verify_scalers(
+        &file,
+        &config.merger_path,
+        &runs,
+        &mut verifier.discrepancies,
+    )?;
+    Ok(verifier.discrepancies)
+}
+
+/// The paths of the parts of an output, starting with the first part.
+fn output_parts(path: &Path, run: i32) -> Vec<PathBuf> {
+    let sync_path = path.parent().unwrap_or(Path::new("."));
+    let mut parts = vec![path.to_path_buf()];
+    let mut part = 1;
+    loop {
+        let part_path = construct_part_path(sync_path, run, part);
+        if !part_path.exists() {
+            break;
+        }
+        parts.push(part_path);
+        part += 1;
+    }
+    parts
+}
+
+/// Read the clock model stored in the sync metadata.
+fn read_clock(meta: &Group) -> Result<ClockModel> {
+    let get_start = meta.dataset("clock_get_start")?.read_raw::<u64>()?;
+    let frib_start = meta.dataset("clock_frib_start")?.read_raw::<f64>()?;
+    let scale = meta.dataset("clock_scale")?.read_raw::<f64>()?;
+    let segments = get_start
+        .into_iter()
+        .zip(frib_start)
+        .zip(scale)
+        .map(|((get_start, frib_start), scale)| ClockSegment {
+            get_start,
+            frib_start,
+            scale,
+        })
+        .collect();
+    Ok(ClockModel { segments })
+}
+
+impl EventVerifier {
+    /// Compare one synchronized event with its source events and check its jitter
+    /// with the previous event.
+    fn verify_event(
+        &mut self,
+        output: &mut MergerReader,
+        events_group: &Group,
+        event: u64,
+    ) -> Result<()> {
+        let event_group = events_group.group(&format!("event_{}", event))?;
+        let get_run = event_group.attr("orig_run")?.read_scalar::<i32>()?;
+        let origin = EventSource {
+            get_run,
+            get_event: event_group.attr("orig_get_event")?.read_scalar::<u64>()?,
+            frib_run: match event_group.attr("orig_frib_run") {
+                Ok(attr) => attr.read_scalar::<i32>()?,
+                Err(_) => get_run,
+            },
+            frib_event: event_group.attr("orig_frib_event")?.read_scalar::<u64>()?,
+        };
+
+        output.current_event = event;
+        let written = output
+            .read_event()?
+            .ok_or_else(|| eyre!("could not be read"))?;
+        self.source.seek(origin.get_run, origin.get_event)?;
+        let source_get = self
+            .source
+            .read_event()?
+            .and_then(|event| event.get)
+            .ok_or_else(|| {
+                eyre!(
+                    "source GET event {}:{} has no GET data",
+                    origin.get_run,
+                    origin.get_event
+                )
+            })?;
+        self.source.seek(origin.frib_run, origin.frib_event)?;
+        let source_frib = self
+            .source
+            .read_event()?
+            .and_then(|event| event.frib)
+            .ok_or_else(|| {
+                eyre!(
+                    "source FRIB event {}:{} has no FRIB data",
+                    origin.frib_run,
+                    origin.frib_event
+                )
+            })?;
+
+        let Some(get) = written.get else {
+            return Err(eyre!("has no GET data"));
+        };
+        let Some(frib) = written.frib else {
+            return Err(eyre!("has no FRIB data"));
+        };
+        if get.traces != source_get.traces
+            || get.id != source_get.id
+            || get.timestamp != source_get.timestamp
+            || get.timestamp_other != source_get.timestamp_other
+        {
+            self.discrepancies.push(format!(
+                "Event {}: GET data differs from source event {}:{}",
+                event, origin.get_run, origin.get_event
+            ));
+        }
+        if frib.traces != source_frib.traces
+            || frib.coincidence != source_frib.coincidence
+            || frib.event != source_frib.event
+            || frib.timestamp != source_frib.timestamp
+        {
+            self.discrepancies.push(format!(
+                "Event {}: FRIB data differs from source event {}:{}",
+                event, origin.frib_run, origin.frib_event
+            ));
+        }
+
+        if let Some(previous) = self.previous.as_ref() {
+            let get_elapsed =
+                self.clock.predict(get.timestamp_other) - self.clock.predict(previous.get);
+            // FRIB time stamps are differenced modulo the counter width to follow wraparounds
+            let frib_elapsed = (frib.timestamp as u64 % self.modulus + self.modulus
+                - previous.frib as u64 % self.modulus)
+                % self.modulus;
+            let jitter = get_elapsed.round() as i64 - frib_elapsed as i64;
+            if jitter.abs() > self.jitter_warning {
+                self.discrepancies.push(format!(
+                    "Event {}: jitter of {} with event {} is above the jitter warning threshold {}",
+                    event, jitter, previous.event, self.jitter_warning
+                ));
+            }
+        }
+        self.previous = Some(PreviousTimestamps {
+            event,
+            get: get.timestamp_other,
+            frib: frib.timestamp,
+        });
+        Ok(())
+    }
+}
+
+/// Check that the output has as many scalers as its merger input.
+fn verify_scalers(
+    file: &File,
+    merger_path: &Path,
+    runs: &[i32],
+    discrepancies: &mut Vec<String>,
+) -> Result<()> {
+    let mut source_count = 0;
+    for run in runs {
+        let source = File::open(construct_run_path(merger_path, *run))?;
+        source_count += count_source_scalers(&source)?;
+    }
+    let output_count = match file.group("scalers") {
+        Ok(group) => group
+            .member_names()?
+            .iter()
+            .filter(|name| name.starts_with("event_"))
+            .count(),
+        Err(_) => 0,
+    };
+    if output_count != source_count {
+        discrepancies.push(format!(
+            "The output has {} scalers, the merger input has {}",
+            output_count, source_count
+        ));
+    }
+    Ok(())
+}
+
+/// Count the scalers of a merger file, as they are copied by the synchronizer.
+fn count_source_scalers(source: &File) -> Result<usize> {
+    let parent_groups = source.member_names()?;
+    if parent_groups.contains(&String::from("meta")) {
+        // Scalers are copied up to the first missing one
+        let group = source.group("frib")?.group("scaler")?;
+        let mut count = 0;
+        while group.dataset(&format!("scaler{count}_data")).is_ok() {
+            count += 1;
+        }
+        Ok(count)
+    } else {
+        let group = source.group("scalers")?;
+        let min = group.attr("min_event")?.read_scalar::<u32>()?;
+        let max = group.attr("max_event")?.read_scalar::<u32>()?;
+        Ok((min..=max)
+            .filter(|scaler| group.dataset(&format!("event{scaler}_data")).is_ok())
+            .count())
+    }
+}
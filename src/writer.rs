@@ -1,38 +1,177 @@
 //! Representation of a Writer for harmonic data
-use super::reader::{construct_run_path, MergerEvent};
-use color_eyre::eyre::Result;
+use super::config::{Compression, Config, OutputConfig};
+use super::reader::{construct_run_path, FribEvent, GetEvent, MergerEvent, MergerReader};
+use color_eyre::eyre::{eyre, Result};
 use hdf5_metno::types::VarLenUnicode;
-use hdf5_metno::File;
+use hdf5_metno::filters::{deflate_available, lzf_available, Filter};
+use hdf5_metno::{Dataset, File, Group, H5Type};
+use log::warn;
+use ndarray::{Array, Dimension};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+/// Construct the temporary path a file is written to before it is complete.
+pub fn construct_temp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+/// Remove the temporary files of a run left in the sync path by an interrupted synchronization.
+/// Only the temporaries of this run are touched, so that another synchronizer can work on
+/// other runs in the same sync path. Returns the paths that were removed.
+pub fn remove_stale_temporaries(sync_path: &Path, run_number: i32) -> Result<Vec<PathBuf>> {
+    let prefix = format!("run_{:0>4}", run_number);
+    let mut removed = Vec::new();
+    for entry in std::fs::read_dir(sync_path)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(suffix) = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".tmp"))
+        else {
+            continue;
+        };
+        let is_part = suffix
+            .strip_prefix("_part")
+            .and_then(|part| part.strip_suffix(".h5"))
+            .is_some_and(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+        if suffix == ".h5" || suffix == "_sync.parquet" || is_part {
+            std::fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+/// The version string written to the outputs of this synchronizer.
+fn synchronizer_version() -> String {
+    format!("{}:{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+/// Check whether the existing output of a run is complete: it was written by this version
+/// of the synchronizer, its first part has the sync_meta group, and each part holds
+/// max_event - min_event events, starting where the previous part ended.
+pub fn is_complete_output(sync_path: &Path, run_number: i32) -> Result<bool> {
+    let mut expected_min = None;
+    let mut part = 0;
+    loop {
+        let path = construct_part_path(sync_path, run_number, part);
+        if part > 0 && !path.exists() {
+            return Ok(true);
+        }
+        let file = File::open(&path)?;
+        if part == 0 && !file.member_names()?.contains(&String::from("sync_meta")) {
+            return Ok(false);
+        }
+        let events_group = file.group("events")?;
+        let version = events_group.attr("version")?.read_scalar::<VarLenUnicode>()?;
+        if version.as_str() != synchronizer_version() {
+            return Ok(false);
+        }
+        let min_event = events_group.attr("min_event")?.read_scalar::<u64>()?;
+        let max_event = events_group.attr("max_event")?.read_scalar::<u64>()?;
+        if expected_min.is_some_and(|expected| expected != min_event) {
+            return Ok(false);
+        }
+        let n_events = events_group
+            .member_names()?
+            .iter()
+            .filter(|name| name.starts_with("event_"))
+            .count() as u64;
+        if max_event < min_event || max_event - min_event != n_events {
+            return Ok(false);
+        }
+        expected_min = Some(max_event);
+        part += 1;
+    }
+}
+
+/// Construct the path of a part of a run's output. The first part (0)
+/// is the usual run path, following parts are suffixed with _part#.
+pub fn construct_part_path(path: &Path, run_number: i32, part: usize) -> PathBuf {
+    if part == 0 {
+        construct_run_path(path, run_number)
+    } else {
+        path.join(format!("run_{:0>4}_part{}.h5", run_number, part))
+    }
+}
+
+/// Remove the files that exist among the given paths. Failures are only reported,
+/// as this is used to clean up after another error.
+fn remove_files(paths: impl IntoIterator<Item = PathBuf>) {
+    for path in paths.into_iter().filter(|path| path.exists()) {
+        if let Err(error) = std::fs::remove_file(&path) {
+            warn!("Could not remove {}: {}", path.display(), error);
+        }
+    }
+}
+
 /// Representation of a writer for harmonic data.
 /// It writes data with a slightly modified version of the
-/// 0.2.0 merger format (see README). Harmonic data is written
-/// to files, where each file has the same total amount of data
-/// (in bytes).
+/// 0.2.0 merger format (see README). If a maximum file size is set,
+/// a run is split into parts once a part reaches that size; event
+/// numbers continue across parts.
 #[derive(Debug)]
 pub struct SyncWriter {
     sync_path: PathBuf,
     current_path: PathBuf,
+    temp_path: PathBuf,
     pub current_file: File,
     pub current_run: i32,
     current_event: u64,
+    filters: Vec<Filter>,
+    chunk_rows: usize,
+    max_file_size: u64,
+    current_part: usize,
+    part_first_event: u64,
+    finished_parts: Vec<(PathBuf, PathBuf)>,
+    companions: Vec<(PathBuf, PathBuf)>,
 }
 
 impl SyncWriter {
     /// Create a new writer, the file to be written is initialized.
-    pub fn new(sync_path: &Path, run: i32) -> Result<Self> {
+    /// The file is written to a temporary path and only moved to its
+    /// final path when the writer is closed.
+    pub fn new(sync_path: &Path, run: i32, output: &OutputConfig) -> Result<Self> {
+        let mut filters = Vec::new();
+        if output.shuffle {
+            filters.push(Filter::Shuffle);
+        }
+        match output.compression {
+            Compression::None => (),
+            Compression::Deflate => {
+                if !deflate_available() {
+                    return Err(eyre!("The deflate filter is not available in this HDF5 library"));
+                }
+                filters.push(Filter::Deflate(output.deflate_level));
+            }
+            Compression::Lzf => {
+                if !lzf_available() {
+                    return Err(eyre!("The LZF filter could not be registered with the HDF5 library"));
+                }
+                filters.push(Filter::LZF);
+            }
+        }
         let current_run = run;
         let current_path = construct_run_path(sync_path, current_run);
-        let current_file = File::create(&current_path)?;
+        let temp_path = construct_temp_path(&current_path);
+        let current_file = File::create(&temp_path)?;
 
         let writer = Self {
             sync_path: sync_path.to_path_buf(),
             current_path,
+            temp_path,
             current_file,
             current_run,
             current_event: 0,
+            filters,
+            chunk_rows: output.chunk_rows,
+            max_file_size: output.max_file_size,
+            current_part: 0,
+            part_first_event: 0,
+            finished_parts: Vec::new(),
+            companions: Vec::new(),
         };
 
         writer.init_file()?;
@@ -63,49 +202,47 @@ impl SyncWriter {
     // }
 
     /// Write a MergerEvent combined from GET and FRIB pieces.
+    /// If the current part has reached the maximum file size, the event
+    /// is written to a new part.
     pub fn write_combined(&mut self, get_event: MergerEvent, frib_event: MergerEvent) -> Result<()> {
+        if self.max_file_size > 0
+            && self.current_event > self.part_first_event
+            && self.current_file.size() >= self.max_file_size
+        {
+            self.next_part()?;
+        }
+
         let event_group = self
             .current_file
             .group("events")?
             .create_group(&format!("event_{}", self.current_event))?;
 
+        event_group
+            .new_attr::<i32>()
+            .create("orig_run")?
+            .write_scalar(&get_event.run_number)?;
+
+        event_group
+            .new_attr::<u64>()
+            .create("orig_get_event")?
+            .write_scalar(&get_event.event)?;
+
+        event_group
+            .new_attr::<u64>()
+            .create("orig_frib_event")?
+            .write_scalar(&frib_event.event)?;
+
+        event_group
+            .new_attr::<i32>()
+            .create("orig_frib_run")?
+            .write_scalar(&frib_event.run_number)?;
+
         if let Some(get) = get_event.get.as_ref() {
-            let traces = event_group
-                .new_dataset_builder()
-                .with_data(&get.traces)
-                .create("get_traces")?;
-            traces
-                .new_attr::<u32>()
-                .create("id")?
-                .write_scalar(&get.id)?;
-            traces
-                .new_attr::<u64>()
-                .create("timestamp")?
-                .write_scalar(&get.timestamp)?;
-            traces
-                .new_attr::<u64>()
-                .create("timestamp_other")?
-                .write_scalar(&get.timestamp_other)?;
+            self.write_get(&event_group, get)?;
         }
 
         if let Some(frib) = frib_event.frib.as_ref() {
-            let frib_group = event_group.create_group("frib_physics")?;
-            frib_group
-                .new_attr::<u32>()
-                .create("event")?
-                .write_scalar(&frib.event)?;
-            frib_group
-                .new_attr::<u32>()
-                .create("timestamp")?
-                .write_scalar(&frib.timestamp)?;
-            frib_group
-                .new_dataset_builder()
-                .with_data(&frib.traces)
-                .create("1903")?;
-            frib_group
-                .new_dataset_builder()
-                .with_data(&frib.coincidence)
-                .create("977")?;
+            self.write_frib(&event_group, frib)?;
         }
 
         self.current_event += 1;
@@ -113,7 +250,138 @@ impl SyncWriter {
         Ok(())
     }
 
-    /// Write a MergerEvent.
+    /// Write the GET side of an event that was not synchronized to
+    /// unmatched/get/event_#, using the original event number.
+    pub fn write_unmatched_get(&self, event: &MergerEvent) -> Result<()> {
+        if let Some(get) = event.get.as_ref() {
+            let event_group = self.create_unmatched_group("get", event)?;
+            self.write_get(&event_group, get)?;
+        }
+        Ok(())
+    }
+
+    /// Write the FRIB side of an event that was not synchronized to
+    /// unmatched/frib/event_#, using the original event number.
+    pub fn write_unmatched_frib(&self, event: &MergerEvent) -> Result<()> {
+        if let Some(frib) = event.frib.as_ref() {
+            let event_group = self.create_unmatched_group("frib", event)?;
+            self.write_frib(&event_group, frib)?;
+        }
+        Ok(())
+    }
+
+    /// Create the group of an unmatched event in unmatched/<side>,
+    /// creating the parent groups if needed. Events from a merged run other
+    /// than the output run are named event_<run>_<event> to keep them apart.
+    fn create_unmatched_group(&self, side: &str, event: &MergerEvent) -> Result<Group> {
+        let unmatched_group = match self.current_file.group("unmatched") {
+            Ok(group) => group,
+            Err(_) => self.current_file.create_group("unmatched")?,
+        };
+        let side_group = match unmatched_group.group(side) {
+            Ok(group) => group,
+            Err(_) => unmatched_group.create_group(side)?,
+        };
+        let name = if event.run_number == self.current_run {
+            format!("event_{}", event.event)
+        } else {
+            format!("event_{}_{}", event.run_number, event.event)
+        };
+        let event_group = side_group.create_group(&name)?;
+        event_group
+            .new_attr::<i32>()
+            .create("orig_run")?
+            .write_scalar(&event.run_number)?;
+        Ok(event_group)
+    }
+
+    /// Write the sync_meta group, which records how this file was synchronized:
+    /// the full configuration, the fitted clock, the first aligned events,
+    /// the mismatch counts and the merger input.
+    pub fn write_meta(&self, config: &Config, reader: &MergerReader) -> Result<()> {
+        let meta_group = self.current_file.create_group("sync_meta")?;
+
+        let config_yaml = serde_yaml::to_string(config)?;
+        meta_group
+            .new_attr::<VarLenUnicode>()
+            .create("config")?
+            .write_scalar(&VarLenUnicode::from_str(&config_yaml)?)?;
+
+        // Merged runs are described by the path of the first run and the total size
+        let input_paths = reader.paths();
+        let input_size = reader.input_bytes()?;
+        let merger_versions = reader.merger_versions()?;
+        meta_group
+            .new_attr::<VarLenUnicode>()
+            .create("input_path")?
+            .write_scalar(&VarLenUnicode::from_str(&input_paths[0].to_string_lossy())?)?;
+        meta_group
+            .new_attr::<u64>()
+            .create("input_size")?
+            .write_scalar(&input_size)?;
+        meta_group
+            .new_attr::<VarLenUnicode>()
+            .create("merger_version")?
+            .write_scalar(&VarLenUnicode::from_str(merger_versions[0].as_str())?)?;
+
+        // Aligned events are reported as merger event numbers, -1 if nothing was aligned
+        let first_get = reader
+            .get_sync
+            .first()
+            .map_or(-1, |i| reader.get_events[*i] as i64);
+        let first_frib = reader
+            .frib_sync
+            .first()
+            .map_or(-1, |i| reader.frib_events[*i] as i64);
+        meta_group
+            .new_attr::<i64>()
+            .create("first_get_event")?
+            .write_scalar(&first_get)?;
+        meta_group
+            .new_attr::<i64>()
+            .create("first_frib_event")?
+            .write_scalar(&first_frib)?;
+        meta_group
+            .new_attr::<u64>()
+            .create("mismatches")?
+            .write_scalar(&((reader.dropped_get.len() + reader.dropped_frib.len()) as u64))?;
+        meta_group
+            .new_attr::<u64>()
+            .create("frib_wraps")?
+            .write_scalar(&reader.frib_wraps)?;
+
+        meta_group
+            .new_dataset_builder()
+            .with_data(reader.runs())
+            .create("input_runs")?;
+        meta_group
+            .new_dataset_builder()
+            .with_data(
+                &merger_versions
+                    .iter()
+                    .map(|version| VarLenUnicode::from_str(version.as_str()))
+                    .collect::<Result<Vec<VarLenUnicode>, _>>()?,
+            )
+            .create("merger_versions")?;
+
+        let segments = &reader.clock.segments;
+        meta_group
+            .new_dataset_builder()
+            .with_data(&segments.iter().map(|s| s.get_start).collect::<Vec<u64>>())
+            .create("clock_get_start")?;
+        meta_group
+            .new_dataset_builder()
+            .with_data(&segments.iter().map(|s| s.frib_start).collect::<Vec<f64>>())
+            .create("clock_frib_start")?;
+        meta_group
+            .new_dataset_builder()
+            .with_data(&segments.iter().map(|s| s.scale).collect::<Vec<f64>>())
+            .create("clock_scale")?;
+
+        Ok(())
+    }
+
+    // Write a MergerEvent.
     // pub fn write(&mut self, event: MergerEvent) -> Result<()> {
     //     let event_group = self
     //         .current_file
@@ -185,25 +453,173 @@ impl SyncWriter {
 
     /// Close the writer, ensuring that the required metadata
     /// is written to the current file.
-    pub fn close(&self) -> Result<()> {
-        self.finish_file()
+    /// The companion files and all parts are then moved from their temporary paths to their
+    /// final paths, the first part last, and parts left over from a previous output are removed.
+    /// If any step fails, the temporary files and the files already moved are removed,
+    /// and the error of the failed step is returned.
+    pub fn close(mut self) -> Result<()> {
+        let finished = self.finish_file();
+        let mut parts = std::mem::take(&mut self.finished_parts);
+        parts.push((self.temp_path.clone(), self.current_path.clone()));
+        let companions = std::mem::take(&mut self.companions);
+        let mut renamed = Vec::new();
+        let result = finished
+            .and_then(|()| Ok(self.current_file.close()?))
+            .and_then(|()| {
+                for (temp_path, path) in companions.iter().chain(parts.iter().rev()) {
+                    std::fs::rename(temp_path, path)?;
+                    renamed.push(path.clone());
+                }
+                let mut part = parts.len();
+                loop {
+                    let stale_path = construct_part_path(&self.sync_path, self.current_run, part);
+                    if !stale_path.exists() {
+                        break;
+                    }
+                    std::fs::remove_file(stale_path)?;
+                    part += 1;
+                }
+                Ok(())
+            });
+        if result.is_err() {
+            let temp_paths = companions.into_iter().chain(parts).map(|(temp_path, _)| temp_path);
+            remove_files(temp_paths.chain(renamed));
+        }
+        result
+    }
+
+    /// Register a file written alongside the output, such as the sync map. The file is to be
+    /// written to the returned temporary path, and is moved to its path when the output is closed.
+    pub fn add_companion(&mut self, path: &Path) -> PathBuf {
+        let temp_path = construct_temp_path(path);
+        self.companions.push((temp_path.clone(), path.to_path_buf()));
+        temp_path
+    }
+
+    /// Abandon the output, removing all the temporary files written so far.
+    pub fn discard(self) {
+        let mut temp_paths: Vec<PathBuf> = self
+            .finished_parts
+            .into_iter()
+            .chain(self.companions)
+            .map(|(temp_path, _)| temp_path)
+            .collect();
+        temp_paths.push(self.temp_path);
+        drop(self.current_file);
+        remove_files(temp_paths);
+    }
+
+    /// Finish the current part and continue writing in the next one.
+    fn next_part(&mut self) -> Result<()> {
+        self.finish_file()?;
+        self.current_part += 1;
+        self.part_first_event = self.current_event;
+        let next_path = construct_part_path(&self.sync_path, self.current_run, self.current_part);
+        let next_temp_path = construct_temp_path(&next_path);
+        let next_file = File::create(&next_temp_path)?;
+        let file = std::mem::replace(&mut self.current_file, next_file);
+        file.close()?;
+        self.finished_parts.push((
+            std::mem::replace(&mut self.temp_path, next_temp_path),
+            std::mem::replace(&mut self.current_path, next_path),
+        ));
+        self.init_file()
     }
 
     /// Initialize the current file
     fn init_file(&self) -> Result<()> {
-        let synchronizer_version =
-            format!("{}:{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let synchronizer_version = synchronizer_version();
 
         let events_group = self.current_file.create_group("events")?;
         events_group
             .new_attr::<u64>()
             .create("min_event")?
-            .write_scalar(&0)?;
+            .write_scalar(&self.part_first_event)?;
         events_group.new_attr::<u64>().create("max_event")?;
         events_group
             .new_attr::<VarLenUnicode>()
             .create("version")?
             .write_scalar(&VarLenUnicode::from_str(&synchronizer_version).unwrap())?;
+        events_group
+            .new_attr::<VarLenUnicode>()
+            .create("filters")?
+            .write_scalar(&VarLenUnicode::from_str(&self.describe_filters())?)?;
+        events_group
+            .new_attr::<u64>()
+            .create("chunk_rows")?
+            .write_scalar(&(self.chunk_rows as u64))?;
+        Ok(())
+    }
+
+    /// Human readable list of the filters applied to the trace datasets.
+    fn describe_filters(&self) -> String {
+        if self.filters.is_empty() {
+            return String::from("none");
+        }
+        self.filters
+            .iter()
+            .map(|filter| match filter {
+                Filter::Shuffle => String::from("shuffle"),
+                Filter::Deflate(level) => format!("deflate({})", level),
+                Filter::LZF => String::from("lzf"),
+                other => format!("{:?}", other),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Create a dataset with the configured filters. Filtered datasets are chunked,
+    /// with at most `chunk_rows` rows per chunk (0 for a single chunk).
+    fn create_dataset<T: H5Type, D: Dimension>(
+        &self,
+        group: &Group,
+        name: &str,
+        data: &Array<T, D>,
+        chunk_rows: usize,
+    ) -> Result<Dataset> {
+        let builder = group.new_dataset_builder().with_data(data);
+        // Empty datasets can't be chunked, so they are always written contiguous
+        if self.filters.is_empty() || data.is_empty() {
+            return Ok(builder.create(name)?);
+        }
+        let mut chunk = data.shape().to_vec();
+        if chunk_rows > 0 {
+            chunk[0] = chunk[0].min(chunk_rows);
+        }
+        Ok(builder.set_filters(&self.filters).chunk(chunk).create(name)?)
+    }
+
+    /// Write the GET traces and their attributes to an event group.
+    fn write_get(&self, event_group: &Group, get: &GetEvent) -> Result<()> {
+        let traces = self.create_dataset(event_group, "get_traces", &get.traces, self.chunk_rows)?;
+        traces
+            .new_attr::<u32>()
+            .create("id")?
+            .write_scalar(&get.id)?;
+        traces
+            .new_attr::<u64>()
+            .create("timestamp")?
+            .write_scalar(&get.timestamp)?;
+        traces
+            .new_attr::<u64>()
+            .create("timestamp_other")?
+            .write_scalar(&get.timestamp_other)?;
+        Ok(())
+    }
+
+    /// Write the FRIB physics data and its attributes to an event group.
+    fn write_frib(&self, event_group: &Group, frib: &FribEvent) -> Result<()> {
+        let frib_group = event_group.create_group("frib_physics")?;
+        frib_group
+            .new_attr::<u32>()
+            .create("event")?
+            .write_scalar(&frib.event)?;
+        frib_group
+            .new_attr::<u32>()
+            .create("timestamp")?
+            .write_scalar(&frib.timestamp)?;
+        self.create_dataset(&frib_group, "1903", &frib.traces, 0)?;
+        self.create_dataset(&frib_group, "977", &frib.coincidence, 0)?;
         Ok(())
     }
 